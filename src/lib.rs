@@ -0,0 +1,51 @@
+//! A fast, multi-threaded port scanner library.
+//!
+//! This crate holds everything the `pscan` command line tool does, so scans can
+//! be embedded in other Rust programs:
+//!
+//! - [`Scanner`] - Builder style API running host discovery and port scans
+//! - [`scanners`] - The individual scan techniques, targets, ports and timing
+//! - [`results`] - Port states, scan results and the per-host scan report
+//! - [`output`] - Writers for the text, JSON, XML, grepable, CSV and NDJSON reports
+//! - [`utils`] - Ping and hostname resolution helpers
+//!
+//! # Example
+//! ```no_run
+//! use pscan::{PortRange, PortState, Scanner};
+//!
+//! let report = Scanner::new("10.0.0.44".parse().unwrap())
+//!     .ports(PortRange::new(1, 1024).unwrap().into())
+//!     .run()
+//!     .unwrap();
+//! for result in &report.hosts[0].results {
+//!     if result.state == PortState::Open {
+//!         println!("{}/{} is open", result.port, result.protocol);
+//!     }
+//! }
+//! ```
+//!
+//! Single hosts can also be scanned directly with one of the [`Scan`]
+//! implementations, without host discovery:
+//!
+//! ```no_run
+//! use pscan::{PortSet, Scan, ScanOptions, TcpScanner};
+//!
+//! let ports: PortSet = "22,80,443".parse().unwrap();
+//! let results = TcpScanner.scan(&"127.0.0.1".parse().unwrap(), &ports, &ScanOptions::default());
+//! ```
+pub mod output;
+pub mod results;
+pub mod scanner;
+pub mod scanners;
+pub mod utils;
+
+pub use results::{
+    CertificateInfo, HostReport, HostStatus, PortState, ScanReport, ScanResult, ScanResults,
+    ServiceInfo, TlsInfo,
+};
+pub use scanner::{ScanObserver, Scanner};
+pub use scanners::{
+    AckScanner, Address, CancelToken, PingProbe, PortRange, PortSet, ResultSink, Scan, ScanOptions,
+    ScanProgress, ScanProtocol, SynScanner, Target, TcpScanType, TcpScanner, TimingTemplate,
+    UdpScanner, WindowScanner,
+};
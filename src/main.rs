@@ -1,83 +1,875 @@
 //! A fast, multi-threaded port scanner implementation in Rust.
 //!
 //! This program provides command-line functionality to scan TCP and UDP
-//! ports on specified IP addresses. It features:
+//! ports on specified hosts and networks. It features:
 //!
-//! - Multi-threaded scanning for improved performance
+//! - Event-driven TCP connect engine with thousands of probes in flight
 //! - Support for both TCP and UDP protocols
-//! - Customizable port ranges
+//! - TCP connect and raw socket SYN (half-open) scans
+//! - TCP ACK and window scans for firewall rule mapping
+//! - Multiple targets given as IPs, hostnames, CIDR blocks or octet ranges
+//! - Target lists read from files or stdin, with exclusion lists
+//! - Customizable port lists, ranges and service names per protocol
+//! - "Top N ports" scanning from a frequency-ranked port list
 //! - Service name resolution using IANA registries
+//! - Banner grabbing of the greetings sent by open TCP ports
+//! - TLS inspection of the protocol, cipher and certificate of open TCP ports
+//! - Service and version detection with a database of protocol probes,
+//!   extensible with nmap-service-probes files
 //! - Filterable output based on port states
-use clap::Parser;
-use scanners::{parse_addr, Address, PortRange, Scan, ScanProtocol, TcpScanner, UdpScanner};
-
-mod results;
-mod scanners;
-mod utils;
+//! - Text, JSON, nmap XML, grepable and CSV reports, printed or written to files
+//! - NDJSON events streamed while the scan runs
+//! - Live progress line, and Ctrl-C stops a scan while keeping its results
+//!
+//! The scanning itself is done by the `pscan` library crate, this program only
+//! turns command line arguments into a [`Scanner`] and writes its reports.
+use clap::{CommandFactory, Parser};
+use pscan::output::ndjson::EventWriter;
+use pscan::output::{self, OutputFormat};
+use pscan::scanners::version::ServiceDatabase;
+use pscan::scanners::{
+    parse_addr, raw, read_address_list, Address, CancelToken, ExcludeList, PingProbe, PortSet,
+    ScanOptions, ScanProgress, ScanProtocol, Target, TcpScanType, TimingTemplate,
+};
+use pscan::{HostReport, PortState, ScanObserver, ScanReport, ScanResult, Scanner};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[doc(hidden)]
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
     #[arg(
-        value_parser = parse_addr, 
-        help = "Target IP address or hostname"
+        required_unless_present = "input_list",
+        value_parser = parse_addr,
+        help = "Target IP addresses, hostnames, CIDR blocks or ranges"
     )]
-    addr: Address,
+    addrs: Vec<Address>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Read targets from a file, or from stdin if FILE is -"
+    )]
+    input_list: Option<String>,
+
+    #[arg(
+        long,
+        value_parser = parse_addr,
+        value_delimiter = ',',
+        help = "Comma separated targets that must not be scanned"
+    )]
+    exclude: Vec<Address>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Read targets to exclude from a file"
+    )]
+    exclude_file: Option<String>,
 
     #[arg(
         short,
         long,
         value_enum,
         default_value_t = ScanProtocol::Tcp,
-        help = "Scan protocol"
+        help = "Scan protocol, overridden by T:/U: qualifiers in the port list"
     )]
     scan_protocol: ScanProtocol,
 
-    #[arg(short, long, default_value_t, help = "Port range to scan")]
-    port_range: PortRange,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = TcpScanType::Connect,
+        help = "TCP scan technique"
+    )]
+    tcp_scan: TcpScanType,
+
+    #[arg(
+        short,
+        long,
+        alias = "port-range",
+        default_value_t,
+        allow_hyphen_values = true,
+        help = "Ports to scan, e.g. 22,80,8000-8100, -1024, ssh,http or T:22,U:53"
+    )]
+    ports: PortSet,
+
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with = "ports",
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Scan the N most commonly open ports, at most the number of ranked ports"
+    )]
+    top_ports: Option<u16>,
+
+    #[arg(
+        short = 'F',
+        long,
+        conflicts_with_all = ["ports", "top_ports"],
+        help = "Fast mode, scan the 100 most commonly open ports"
+    )]
+    fast: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["skip_ping", "ports", "top_ports", "fast", "tcp_scan"],
+        help = "Only discover which hosts are up, without scanning ports (-sn)"
+    )]
+    discover_only: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["echo_ping", "timestamp_ping", "syn_ping", "ack_ping", "udp_ping"],
+        help = "Skip host discovery and scan every target (-Pn)"
+    )]
+    skip_ping: bool,
+
+    #[arg(
+        long,
+        help = "Discover hosts with ICMP echo (-PE), the default if no other ping is given"
+    )]
+    echo_ping: bool,
+
+    #[arg(long, help = "Discover hosts with ICMP timestamp requests (-PP)")]
+    timestamp_ping: bool,
+
+    #[arg(
+        long,
+        value_name = "PORTS",
+        num_args = 0..=1,
+        require_equals = true,
+        value_delimiter = ',',
+        default_missing_value = "80",
+        help = "Discover hosts with TCP SYN to PORTS (-PS) [default: 80]"
+    )]
+    syn_ping: Option<Vec<u16>>,
+
+    #[arg(
+        long,
+        value_name = "PORTS",
+        num_args = 0..=1,
+        require_equals = true,
+        value_delimiter = ',',
+        default_missing_value = "80",
+        help = "Discover hosts with TCP ACK to PORTS (-PA) [default: 80]"
+    )]
+    ack_ping: Option<Vec<u16>>,
+
+    #[arg(
+        long,
+        value_name = "PORTS",
+        num_args = 0..=1,
+        require_equals = true,
+        value_delimiter = ',',
+        default_missing_value = "40125",
+        help = "Discover hosts with UDP datagrams to PORTS (-PU) [default: 40125]"
+    )]
+    udp_ping: Option<Vec<u16>>,
+
+    #[arg(
+        long,
+        conflicts_with = "discover_only",
+        help = "Read the greeting sent by each open TCP port, such as an SSH or SMTP banner"
+    )]
+    banners: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "discover_only",
+        help = "Complete a TLS handshake with each open TCP port and record its protocol, \
+                cipher and certificate"
+    )]
+    tls: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "discover_only",
+        help = "Probe open ports to identify their service, product and version (-sV)"
+    )]
+    version_detection: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "version_detection",
+        help = "Add the probes of an nmap-service-probes FILE to version detection, can be repeated"
+    )]
+    service_probes: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "0-9",
+        requires = "version_detection",
+        value_parser = clap::value_parser!(u8).range(0..=9),
+        help = "Also send version detection probes up to this rarity to ports they are \
+                not registered for [default: 7]"
+    )]
+    version_intensity: Option<u8>,
 
     #[arg(short, long, help = "Port states ignored in the scan output")]
-    ignored_state: Vec<results::PortState>,
+    ignored_state: Vec<PortState>,
+
+    #[arg(
+        short = 'o',
+        long,
+        value_enum,
+        help = "Format of the scan report, repeat to write several formats [default: text]"
+    )]
+    output_format: Vec<OutputFormat>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write the report to PATH, or each of several formats to PATH.<ext>, \
+                the text report is still printed to stdout"
+    )]
+    output: Option<String>,
+
+    #[arg(
+        long,
+        help = "Do not show the progress line, which is only shown when stderr is a terminal"
+    )]
+    no_progress: bool,
+
+    #[arg(
+        short = 'T',
+        long,
+        value_enum,
+        default_value_t = TimingTemplate::Normal,
+        help = "Timing template (0-5), sets the timing options below unless given explicitly"
+    )]
+    timing: TimingTemplate,
+
+    #[arg(
+        short,
+        long,
+        help = "Probe timeout in ms until the round trip time is known [default: 25]"
+    )]
+    timeout: Option<u64>,
 
-    #[arg(short, long, default_value_t = 25, help = "Connection timeout in ms")]
-    timeout: u64,
+    #[arg(
+        long,
+        help = "Maximum number of retransmissions of an unanswered probe [default: 1]"
+    )]
+    max_retries: Option<u32>,
+
+    #[arg(
+        long,
+        help = "Lower bound of the adaptive probe timeout in ms [default: 10]"
+    )]
+    min_rtt_timeout: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Upper bound of the adaptive probe timeout in ms [default: 1000]"
+    )]
+    max_rtt_timeout: Option<u64>,
+
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Maximum number of probes in flight at once [default: 1024]"
+    )]
+    max_parallelism: Option<u64>,
+
+    #[arg(long, help = "Minimum delay between probes in ms [default: 0]")]
+    scan_delay: Option<u64>,
+
+    #[arg(
+        long,
+        value_parser = parse_rate,
+        help = "Send at least this many probes per second"
+    )]
+    min_rate: Option<f64>,
+
+    #[arg(
+        long,
+        value_parser = parse_rate,
+        help = "Send at most this many probes per second"
+    )]
+    max_rate: Option<f64>,
+}
+
+#[doc(hidden)]
+fn read_service_probes(path: &str) -> Result<ServiceDatabase, Box<dyn std::error::Error>> {
+    let (database, errors) =
+        ServiceDatabase::read_file(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    for error in errors {
+        eprintln!("Warning: {}", error);
+    }
+
+    Ok(database)
 }
 
 #[doc(hidden)]
-fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
-    let addr = match args.addr {
-        Address::Ip(ip) => ip,
-        Address::Hostname(ref hostname) => utils::resolve_hostname_to_ip(hostname)
-            .ok_or(format!("Could not resolve hostname {}", hostname))?,
+fn read_list(path: &str) -> Result<Vec<Address>, Box<dyn std::error::Error>> {
+    let (addrs, errors) =
+        read_address_list(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    for error in errors {
+        eprintln!("Warning: {}", error);
+    }
+
+    Ok(addrs)
+}
+
+/// Rewrites nmap style flags such as `-sn`, `-sV`, `-Pn` and `-PS22,443`
+/// into their long forms, since clap short flags are a single character.
+///
+/// Only arguments in flag position are rewritten: the program name, option
+/// values given as a separate argument and everything after `--` are passed on
+/// untouched, as are arguments that are not valid UTF-8 for clap to report.
+#[doc(hidden)]
+fn expand_nmap_flags(args: impl Iterator<Item = OsString>) -> Vec<OsString> {
+    let command = Args::command();
+    let mut expanded = Vec::new();
+    let mut value_expected = false;
+    let mut separator_seen = false;
+
+    for (i, arg) in args.enumerate() {
+        let flag_position = i > 0 && !separator_seen && !value_expected;
+        value_expected = false;
+        if flag_position {
+            let text = arg.to_string_lossy().into_owned();
+            if text == "--" {
+                separator_seen = true;
+            } else if let Some(long) = expand_nmap_flag(&text) {
+                expanded.push(OsString::from(long));
+                continue;
+            } else {
+                value_expected = takes_next_value(&command, &text);
+            }
+        }
+        expanded.push(arg);
+    }
+
+    expanded
+}
+
+/// Returns true if `arg` is an option whose value is the next argument.
+#[doc(hidden)]
+fn takes_next_value(command: &clap::Command, arg: &str) -> bool {
+    let separate_value =
+        |option: &clap::Arg| option.get_action().takes_values() && !option.is_require_equals_set();
+
+    if let Some(long) = arg.strip_prefix("--") {
+        return !long.contains('=')
+            && command.get_arguments().any(|option| {
+                let names = option
+                    .get_long()
+                    .into_iter()
+                    .chain(option.get_all_aliases().unwrap_or_default());
+                separate_value(option) && names.into_iter().any(|name| name == long)
+            });
+    }
+
+    // Short flags may be grouped, and the first one taking a value consumes
+    // the rest of the argument, or the next argument if nothing is left.
+    let Some(shorts) = arg.strip_prefix('-') else {
+        return false;
     };
+    for (i, c) in shorts.char_indices() {
+        let option = command.get_arguments().find(|option| {
+            option.get_short() == Some(c)
+                || option
+                    .get_all_short_aliases()
+                    .unwrap_or_default()
+                    .contains(&c)
+        });
+        match option {
+            Some(option) if option.get_action().takes_values() => {
+                return i + c.len_utf8() == shorts.len() && !option.is_require_equals_set();
+            }
+            Some(_) => {}
+            None => return false,
+        }
+    }
+    false
+}
+
+/// Returns the long form of a single nmap style flag, or `None` if it has none.
+#[doc(hidden)]
+fn expand_nmap_flag(arg: &str) -> Option<String> {
+    match arg {
+        "-sn" => return Some("--discover-only".to_string()),
+        "-sV" => return Some("--version-detection".to_string()),
+        _ => {}
+    }
+    let long = match arg.get(..3)? {
+        "-Pn" => "--skip-ping",
+        "-PE" => "--echo-ping",
+        "-PP" => "--timestamp-ping",
+        "-PS" => "--syn-ping",
+        "-PA" => "--ack-ping",
+        "-PU" => "--udp-ping",
+        _ => return None,
+    };
+    match &arg[3..] {
+        "" => Some(long.to_string()),
+        ports => Some(format!("{}={}", long, ports)),
+    }
+}
+
+/// Returns the host discovery probes to send, or `None` if discovery is skipped.
+#[doc(hidden)]
+fn discovery_probes(args: &Args) -> Result<Option<Vec<PingProbe>>, Box<dyn std::error::Error>> {
+    if args.skip_ping {
+        return Ok(None);
+    }
+
+    let mut probes = Vec::new();
+    if args.echo_ping {
+        probes.push(PingProbe::Echo);
+    }
+    if args.timestamp_ping {
+        probes.push(PingProbe::Timestamp);
+    }
+    probes.extend(args.syn_ping.iter().flatten().map(|&p| PingProbe::Syn(p)));
+    probes.extend(args.ack_ping.iter().flatten().map(|&p| PingProbe::Ack(p)));
+    probes.extend(args.udp_ping.iter().flatten().map(|&p| PingProbe::Udp(p)));
+    if probes.is_empty() {
+        probes.push(PingProbe::Echo);
+    }
+
+    if !raw::has_raw_privileges() {
+        probes = probes
+            .into_iter()
+            .filter_map(|probe| {
+                let fallback = probe.unprivileged();
+                match &fallback {
+                    Some(f) if *f == probe => {}
+                    Some(f) => eprintln!(
+                        "Warning: {} requires root or CAP_NET_RAW, using {} instead",
+                        probe, f
+                    ),
+                    None => eprintln!("Warning: {} requires root or CAP_NET_RAW, skipping", probe),
+                }
+                fallback
+            })
+            .collect();
+        if probes.is_empty() {
+            return Err("No usable host discovery probes, use --skip-ping to scan anyway".into());
+        }
+    }
+
+    Ok(Some(probes))
+}
+
+#[doc(hidden)]
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!("Invalid rate {}, expected a positive number", s)),
+    }
+}
 
-    match utils::ping_host(&addr) { 
-        Ok(reply) => println!("Host is up ({}ms latency).", reply.rtt),
-        Err(e) => return Err(format!("Host is unreachable, {:?}", e).into()),
+#[doc(hidden)]
+fn scan_options(args: &Args) -> Result<ScanOptions, Box<dyn std::error::Error>> {
+    let mut options = ScanOptions::from(args.timing);
+    options.timeout_ms = args.timeout.unwrap_or(options.timeout_ms);
+    options.max_retries = args.max_retries.unwrap_or(options.max_retries);
+    options.min_rtt_timeout_ms = args.min_rtt_timeout.unwrap_or(options.min_rtt_timeout_ms);
+    options.max_rtt_timeout_ms = args.max_rtt_timeout.unwrap_or(options.max_rtt_timeout_ms);
+    if let Some(n) = args.max_parallelism {
+        options.max_parallelism = n as usize;
     }
+    options.scan_delay_ms = args.scan_delay.unwrap_or(options.scan_delay_ms);
+    options.min_rate = args.min_rate;
+    options.max_rate = args.max_rate;
 
-    let get_scanner = |protocol: &ScanProtocol| -> Box<dyn Scan> {
-        match protocol {
-            ScanProtocol::Tcp => Box::new(TcpScanner),
-            ScanProtocol::Udp => Box::new(UdpScanner),
+    if options.min_rtt_timeout_ms > options.max_rtt_timeout_ms {
+        return Err("--min-rtt-timeout must not be greater than --max-rtt-timeout".into());
+    }
+    if let (Some(min), Some(max)) = (options.min_rate, options.max_rate) {
+        if min > max {
+            return Err("--min-rate must not be greater than --max-rate".into());
         }
+    }
+
+    Ok(options)
+}
+
+/// Returns the requested report formats without duplicates, text if none were given.
+///
+/// Only one report fits on stdout, so writing several formats needs `--output`.
+#[doc(hidden)]
+fn output_formats(args: &Args) -> Result<Vec<OutputFormat>, Box<dyn std::error::Error>> {
+    let mut formats = Vec::new();
+    for format in &args.output_format {
+        if !formats.contains(format) {
+            formats.push(*format);
+        }
+    }
+    if formats.is_empty() {
+        formats.push(OutputFormat::Text);
+    }
+
+    if formats.len() > 1 && args.output.is_none() {
+        return Err("Writing more than one output format requires --output".into());
+    }
+    Ok(formats)
+}
+
+/// Number of ports scanned by `--fast`.
+#[doc(hidden)]
+const FAST_TOP_PORTS: u16 = 100;
+
+/// Minimum time between redraws of the progress line.
+#[doc(hidden)]
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Prints the text report of each host and streams NDJSON events while the scan runs.
+#[doc(hidden)]
+struct Progress {
+    /// The scanned ports and ignored states, if host reports are printed
+    text: Option<(String, Vec<PortState>)>,
+    events: Option<EventWriter>,
+    warn_down: bool,
+    /// When the progress line was last drawn, if it is shown
+    status_line: Option<Mutex<Option<Instant>>>,
+}
+
+impl Progress {
+    /// Erases the progress line so other output starts on a clean line.
+    fn clear_status_line(&self) {
+        if let Some(ref last_drawn) = self.status_line {
+            let mut last_drawn = last_drawn.lock().unwrap();
+            if last_drawn.take().is_some() {
+                eprint!("\r\x1b[K");
+            }
+        }
+    }
+}
+
+/// Formats a duration as minutes and seconds, e.g. `2:05`.
+#[doc(hidden)]
+fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+impl ScanObserver for Progress {
+    fn target_skipped(&self, error: &str) -> io::Result<()> {
+        eprintln!("Warning: {}", error);
+        Ok(())
+    }
+
+    fn scan_started(&self, report: &ScanReport, hosts_total: usize) -> io::Result<()> {
+        match self.events {
+            Some(ref events) => events.scan_started(report, hosts_total),
+            None => Ok(()),
+        }
+    }
+
+    fn host_up(&self, host: &HostReport) -> io::Result<()> {
+        match self.events {
+            Some(ref events) => events.host_up(host),
+            None => Ok(()),
+        }
+    }
+
+    fn port_result(&self, target: &Target, result: &ScanResult) -> io::Result<()> {
+        match self.events {
+            Some(ref events) => events.port_result(target, result),
+            None => Ok(()),
+        }
+    }
+
+    fn port_updated(&self, target: &Target, result: &ScanResult) -> io::Result<()> {
+        match self.events {
+            Some(ref events) => events.port_updated(target, result),
+            None => Ok(()),
+        }
+    }
+
+    fn progress(&self, progress: &ScanProgress) -> io::Result<()> {
+        let Some(ref last_drawn) = self.status_line else {
+            return Ok(());
+        };
+        let mut last_drawn = last_drawn.lock().unwrap();
+        if last_drawn.is_some_and(|drawn| drawn.elapsed() < PROGRESS_INTERVAL) {
+            return Ok(());
+        }
+        *last_drawn = Some(Instant::now());
+
+        let eta = progress
+            .eta()
+            .map(format_eta)
+            .unwrap_or_else(|| "-".to_string());
+        eprint!(
+            "\r\x1b[KScanned {}/{} ports ({:.1}%), {} open, ETA {}",
+            progress.ports_done,
+            progress.ports_total,
+            progress.percent(),
+            progress.open,
+            eta
+        );
+        Ok(())
+    }
+
+    fn host_done(&self, host: &HostReport) -> io::Result<()> {
+        self.clear_status_line();
+        if self.warn_down && !host.status.is_up() {
+            eprintln!(
+                "Host {} is unreachable, no discovery probe was answered",
+                host.target
+            );
+        }
+        if let Some((ref ports, ref ignored)) = self.text {
+            output::text::write_host(&mut io::stdout(), host, ports, ignored)?;
+        }
+        match self.events {
+            Some(ref events) => events.host_done(host),
+            None => Ok(()),
+        }
+    }
+
+    fn scan_finished(&self, report: &ScanReport) -> io::Result<()> {
+        self.clear_status_line();
+        match self.events {
+            Some(ref events) => events.scan_finished(report),
+            None => Ok(()),
+        }
+    }
+}
+
+#[doc(hidden)]
+fn run(mut args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(n) = args.top_ports.or(args.fast.then_some(FAST_TOP_PORTS)) {
+        let ranked = PortSet::ranked_len(&args.scan_protocol);
+        if usize::from(n) > ranked {
+            eprintln!(
+                "Warning: Only {} {} ports are ranked, scanning the top {}",
+                ranked,
+                args.scan_protocol.to_string().to_uppercase(),
+                ranked
+            );
+        }
+        args.ports = PortSet::top(usize::from(n).min(ranked));
+    }
+
+    let mut addrs = args.addrs.clone();
+    if let Some(ref path) = args.input_list {
+        addrs.extend(read_list(path)?);
+    }
+
+    let mut excluded = args.exclude.clone();
+    if let Some(ref path) = args.exclude_file {
+        excluded.extend(read_list(path)?);
+    }
+
+    // Probe files add to the built-in database rather than replace it.
+    let mut services = None;
+    if !args.service_probes.is_empty() {
+        let mut database = ServiceDatabase::clone(&ServiceDatabase::builtin());
+        for path in &args.service_probes {
+            database.merge(read_service_probes(path)?);
+        }
+        services = Some(Arc::new(database));
+    }
+
+    if args.tcp_scan.is_raw() && !raw::has_raw_privileges() {
+        if args.tcp_scan != TcpScanType::Syn {
+            return Err(format!("{} scan requires root or CAP_NET_RAW", args.tcp_scan).into());
+        }
+        eprintln!(
+            "Warning: {} scan requires root or CAP_NET_RAW, falling back to TCP connect scan",
+            args.tcp_scan
+        );
+        args.tcp_scan = TcpScanType::Connect;
+    }
+
+    let options = scan_options(&args)?;
+    let probes = discovery_probes(&args)?;
+    let formats = output_formats(&args)?;
+    // Fail before scanning if a report cannot be written.
+    let mut output_files = Vec::new();
+    if let Some(ref path) = args.output {
+        for format in &formats {
+            let path = match formats.len() {
+                1 => path.clone(),
+                _ => format!("{}.{}", path, format.extension()),
+            };
+            let file =
+                File::create(&path).map_err(|e| format!("Could not create {}: {}", path, e))?;
+            output_files.push((*format, path, BufWriter::new(file)));
+        }
+    }
+
+    // The text report is printed while scanning unless stdout gets another format.
+    let print_text = args.output.is_some() || formats == [OutputFormat::Text];
+    // NDJSON events are streamed while scanning instead of written at the end.
+    let ndjson_file = output_files
+        .iter()
+        .position(|(format, _, _)| *format == OutputFormat::Ndjson)
+        .map(|i| output_files.remove(i));
+    let events = match ndjson_file {
+        Some((_, _, file)) => Some(EventWriter::new(Box::new(file))),
+        None if formats == [OutputFormat::Ndjson] => Some(EventWriter::new(Box::new(io::stdout()))),
+        None => None,
+    };
+    let stream_events = events.is_some();
+    let progress = Progress {
+        text: (print_text && !args.discover_only)
+            .then(|| (args.ports.to_string(), args.ignored_state.clone())),
+        events,
+        warn_down: !args.discover_only,
+        status_line: (!args.no_progress && !args.discover_only && io::stderr().is_terminal())
+            .then(|| Mutex::new(None)),
     };
-    let scanner = get_scanner(&args.scan_protocol);
-    let start_time = std::time::Instant::now();
-    let results = scanner.scan(&addr, &args.port_range, args.timeout);
-    let duration = start_time.elapsed();
 
-    results::print_results(&args, results, duration);
+    // The first Ctrl-C stops the scan and keeps its results, a second one quits.
+    let cancel = CancelToken::new();
+    let handler_cancel = cancel.clone();
+    ctrlc::set_handler(move || {
+        if handler_cancel.is_cancelled() {
+            std::process::exit(130);
+        }
+        eprintln!("\nInterrupted, writing the results so far (press Ctrl-C again to quit)");
+        handler_cancel.cancel();
+    })?;
+
+    let mut scanner = Scanner::default()
+        .targets(addrs)
+        .exclude(ExcludeList::new(excluded))
+        .ports(args.ports.clone())
+        .protocol(args.scan_protocol.clone())
+        .tcp_scan(args.tcp_scan.clone())
+        .options(options)
+        .cancel_token(cancel)
+        .discover_only(args.discover_only)
+        .banners(args.banners)
+        .tls(args.tls)
+        .version_detection(args.version_detection)
+        .command(
+            std::env::args_os()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+    if let Some(probes) = probes {
+        scanner = scanner.discovery(probes);
+    }
+    if let Some(services) = services {
+        scanner = scanner.service_database(services);
+    }
+    if let Some(intensity) = args.version_intensity {
+        scanner = scanner.version_intensity(intensity);
+    }
+    let report = scanner.run_with(Arc::new(progress))?;
+
+    let mut stdout = io::stdout();
+    if print_text {
+        if args.discover_only {
+            output::text::write_live_hosts(&mut stdout, &report.hosts)?;
+            writeln!(stdout)?;
+        }
+        output::text::write_summary(&mut stdout, &report)?;
+    }
+
+    for (format, path, mut file) in output_files {
+        output::write_report(&mut file, format, &report, &args.ignored_state)
+            .and_then(|_| file.flush())
+            .map_err(|e| format!("Could not write {}: {}", path, e))?;
+    }
+    if !print_text && !stream_events {
+        output::write_report(&mut stdout, formats[0], &report, &args.ignored_state)?;
+    }
 
+    if report.interrupted {
+        return Err(
+            "Scan interrupted, the reports only include the hosts and ports scanned so far".into(),
+        );
+    }
     Ok(())
 }
 
 #[doc(hidden)]
 fn main() {
-    let args = Args::parse();
+    let args = Args::parse_from(expand_nmap_flags(std::env::args_os()));
     if let Err(e) = run(args) {
         eprintln!("Error: {}", e);
         std::process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(args: &[&str]) -> Vec<String> {
+        let args = args.iter().map(OsString::from);
+        expand_nmap_flags(args)
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn nmap_flags_are_expanded_in_flag_position() {
+        assert_eq!(
+            expand(&[
+                "pscan",
+                "-Pn",
+                "-sn",
+                "-sV",
+                "-PS22,443",
+                "-PU",
+                "localhost"
+            ]),
+            [
+                "pscan",
+                "--skip-ping",
+                "--discover-only",
+                "--version-detection",
+                "--syn-ping=22,443",
+                "--udp-ping",
+                "localhost"
+            ]
+        );
+    }
+
+    #[test]
+    fn nmap_flags_are_not_expanded_in_option_values() {
+        assert_eq!(
+            expand(&[
+                "pscan",
+                "--exclude",
+                "-Pn",
+                "-p",
+                "-PS",
+                "-T4",
+                "-t",
+                "-sn",
+                "-PE"
+            ]),
+            [
+                "pscan",
+                "--exclude",
+                "-Pn",
+                "-p",
+                "-PS",
+                "-T4",
+                "-t",
+                "-sn",
+                "--echo-ping"
+            ]
+        );
+        assert_eq!(
+            expand(&["pscan", "--exclude=-Pn", "-p80", "-PA"]),
+            ["pscan", "--exclude=-Pn", "-p80", "--ack-ping"]
+        );
+    }
+
+    #[test]
+    fn nmap_flags_are_not_expanded_after_separator() {
+        assert_eq!(
+            expand(&["pscan", "-Pn", "--", "-Pn", "-sV"]),
+            ["pscan", "--skip-ping", "--", "-Pn", "-sV"]
+        );
+    }
+}
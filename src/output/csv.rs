@@ -0,0 +1,95 @@
+//! CSV output.
+//!
+//! One row per scanned port, including ports in the ignored states, with a
+//! header row naming the columns:
+//!
+//! ```text
+//! host,ip,port,protocol,state,service,reason,rtt_ms,banner,confidence,product,version,extra_info,tls_version,tls_cipher,tls_upgrade,tls_alpn,cert_subject,cert_issuer,cert_sans,cert_not_before,cert_not_after,cert_days_left,cert_self_signed,cert_sha256
+//! 10.0.0.44,10.0.0.44,22,tcp,open,ssh,syn-ack,0.312,SSH-2.0-OpenSSH_9.6,10,OpenSSH,9.6,protocol 2.0,,,,,,,,,,,,
+//! 10.0.0.44,10.0.0.44,443,tcp,open,https,syn-ack,0.208,,3,,,,TLSv1.3,TLS13_AES_256_GCM_SHA384,,h2,CN=example.com,CN=example.com,example.com www.example.com,2025-01-01T00:00:00Z,2026-01-01T00:00:00Z,76,true,3A:9F:...:C4
+//! ```
+//!
+//! `host` is the hostname the target was given by, or its IP address. The
+//! `service` is the one identified by version detection if it ran, or else the
+//! name registered for the port. The `tls_` and `cert_` fields describe the
+//! TLS handshake and certificate of the port if TLS inspection ran, with the
+//! subject alternative names separated by spaces. Fields are empty when
+//! unknown. Hosts that are down have no rows.
+use crate::output::format_utc;
+use crate::results::ScanReport;
+use std::io::{self, Write};
+
+/// The header row.
+const HEADER: &str = "host,ip,port,protocol,state,service,reason,rtt_ms,banner,\
+    confidence,product,version,extra_info,\
+    tls_version,tls_cipher,tls_upgrade,tls_alpn,cert_subject,cert_issuer,cert_sans,\
+    cert_not_before,cert_not_after,cert_days_left,cert_self_signed,cert_sha256";
+
+/// Writes a scan report as CSV.
+pub fn write_report(out: &mut dyn Write, report: &ScanReport) -> io::Result<()> {
+    writeln!(out, "{}", HEADER)?;
+    for host in &report.hosts {
+        let ip = host.target.ip.to_string();
+        let name = host.target.hostname.as_deref().unwrap_or(&ip);
+        for result in &host.results {
+            let detected = result.service.as_ref();
+            let optional_field = |value: Option<&String>| field(value.map_or("", String::as_str));
+            let tls = result.tls.as_ref();
+            let cert = tls.and_then(|tls| tls.certificate.as_ref());
+            write!(
+                out,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},",
+                field(name),
+                ip,
+                result.port,
+                result.protocol,
+                field(&result.state.to_string()),
+                field(result.service_name().unwrap_or_default()),
+                result.reason,
+                result
+                    .rtt
+                    .map(|rtt| format!("{:.3}", rtt.as_secs_f64() * 1000.0))
+                    .unwrap_or_default(),
+                field(result.banner.as_deref().unwrap_or_default()),
+                result
+                    .service_confidence()
+                    .map(|confidence| confidence.to_string())
+                    .unwrap_or_default(),
+                optional_field(detected.and_then(|service| service.product.as_ref())),
+                optional_field(detected.and_then(|service| service.version.as_ref())),
+                optional_field(detected.and_then(|service| service.extra_info.as_ref()))
+            )?;
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{},{},{},{}",
+                tls.map(|tls| field(&tls.version)).unwrap_or_default(),
+                tls.map(|tls| field(&tls.cipher_suite)).unwrap_or_default(),
+                tls.and_then(|tls| tls.upgrade).unwrap_or_default(),
+                optional_field(tls.and_then(|tls| tls.alpn.as_ref())),
+                cert.map(|cert| field(&cert.subject)).unwrap_or_default(),
+                cert.map(|cert| field(&cert.issuer)).unwrap_or_default(),
+                cert.map(|cert| field(&cert.sans.join(" ")))
+                    .unwrap_or_default(),
+                cert.map(|cert| format_utc(cert.not_before))
+                    .unwrap_or_default(),
+                cert.map(|cert| format_utc(cert.not_after))
+                    .unwrap_or_default(),
+                cert.map(|cert| cert.days_left().to_string())
+                    .unwrap_or_default(),
+                cert.map(|cert| cert.is_self_signed().to_string())
+                    .unwrap_or_default(),
+                cert.map(|cert| cert.sha256.clone()).unwrap_or_default()
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Quotes a field if it contains a comma, quote or line break, as described by RFC 4180.
+fn field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
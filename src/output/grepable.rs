@@ -0,0 +1,122 @@
+//! Grepable output.
+//!
+//! Follows nmap's grepable format: every host gets a `Status` line and, if its
+//! ports were scanned, a single `Ports` line, so one `grep` pulls out what is
+//! needed:
+//!
+//! ```text
+//! # pscan 0.1.0 scan initiated as: pscan 10.0.0.44 -p 22,80,443 -o grepable -i closed
+//! Host: 10.0.0.44 ()    Status: Up
+//! Host: 10.0.0.44 ()    Ports: 22/open/tcp//ssh///, 80/open/tcp//www-http///    Ignored State: closed (1)
+//! # pscan done -- 1 IP address (1 host up) scanned in 0.01 seconds
+//! ```
+//!
+//! Each port is written as `port/state/protocol/owner/service/rpc/version/`,
+//! the owner and RPC fields are always empty and the version field holds the
+//! product, version and extra info found by version detection. Slashes and
+//! commas inside fields are replaced so the fields can still be split. Fields
+//! are separated by tabs. The footer reads `# pscan interrupted` instead of
+//! `# pscan done` when the scan was cancelled before it finished.
+use crate::results::{HostReport, PortState, ScanReport};
+use std::io::{self, Write};
+
+/// Writes a scan report in the grepable format.
+pub fn write_report(
+    out: &mut dyn Write,
+    report: &ScanReport,
+    ignored: &[PortState],
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "# {} {} scan initiated as: {}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        report.command
+    )?;
+    for host in &report.hosts {
+        write_host(out, host, report.ports.is_some(), ignored)?;
+    }
+
+    let n_targets = report.hosts.len();
+    let n_up = report.hosts_up();
+    writeln!(
+        out,
+        "# {} {} -- {} IP address{} ({} host{} up) scanned in {:.2} seconds",
+        env!("CARGO_PKG_NAME"),
+        match report.interrupted {
+            true => "interrupted",
+            false => "done",
+        },
+        n_targets,
+        if n_targets == 1 { "" } else { "es" },
+        n_up,
+        if n_up == 1 { "" } else { "s" },
+        report.duration.as_secs_f64()
+    )
+}
+
+/// Writes the status line of a host, followed by its ports line if its ports were scanned.
+fn write_host(
+    out: &mut dyn Write,
+    host: &HostReport,
+    ports_scanned: bool,
+    ignored: &[PortState],
+) -> io::Result<()> {
+    let prefix = format!(
+        "Host: {} ({})",
+        host.target.ip,
+        host.target.hostname.as_deref().unwrap_or_default()
+    );
+    let status = match host.status.is_up() {
+        true => "Up",
+        false => "Down",
+    };
+    writeln!(out, "{}\tStatus: {}", prefix, status)?;
+    if !ports_scanned || !host.status.is_up() {
+        return Ok(());
+    }
+
+    let ports = host
+        .results
+        .iter()
+        .filter(|r| !ignored.contains(&r.state))
+        .map(|r| {
+            format!(
+                "{}/{}/{}//{}//{}/",
+                r.port,
+                r.state,
+                r.protocol,
+                field(r.service_name().unwrap_or_default()),
+                field(
+                    &r.service
+                        .as_ref()
+                        .and_then(|service| service.version_line())
+                        .unwrap_or_default()
+                )
+            )
+        })
+        .collect::<Vec<_>>();
+    write!(out, "{}\tPorts: {}", prefix, ports.join(", "))?;
+
+    let ignored_counts = ignored
+        .iter()
+        .map(|state| {
+            (
+                state,
+                host.results.iter().filter(|r| r.state == *state).count(),
+            )
+        })
+        .filter(|(_, count)| *count > 0)
+        .map(|(state, count)| format!("{} ({})", state, count))
+        .collect::<Vec<_>>();
+    if !ignored_counts.is_empty() {
+        write!(out, "\tIgnored State: {}", ignored_counts.join(", "))?;
+    }
+
+    writeln!(out)
+}
+
+/// Replaces the characters that separate ports and their fields, as nmap does.
+fn field(value: &str) -> String {
+    value.replace('/', "|").replace(',', ";")
+}
@@ -0,0 +1,301 @@
+//! JSON output.
+//!
+//! The report is written as a single JSON document whose layout is versioned by
+//! its `format_version` field. The version is only bumped for changes that can
+//! break existing consumers, such as removing or renaming a field; new fields
+//! may be added at any time.
+//!
+//! ```json
+//! {
+//!   "format_version": 1,
+//!   "scanner": "pscan",
+//!   "scanner_version": "0.1.0",
+//!   "command": "pscan 10.0.0.44 -p 54000-60000 -o json",
+//!   "start_time": 1737590400,
+//!   "duration_secs": 0.08,
+//!   "interrupted": false,
+//!   "ports": "54000-60000",
+//!   "hosts_total": 1,
+//!   "hosts_up": 1,
+//!   "hosts": [
+//!     {
+//!       "target": "10.0.0.44",
+//!       "hostname": null,
+//!       "ip": "10.0.0.44",
+//!       "status": "up",
+//!       "latency_ms": 0.4,
+//!       "reason": "echo-reply",
+//!       "mac": null,
+//!       "vendor": null,
+//!       "ports": [
+//!         {
+//!           "port": 54446,
+//!           "protocol": "tcp",
+//!           "state": "open",
+//!           "reason": "syn-ack",
+//!           "rtt_ms": 0.1,
+//!           "service": null,
+//!           "confidence": null,
+//!           "product": null,
+//!           "version": null,
+//!           "extra_info": null,
+//!           "service_hostname": null,
+//!           "os": null,
+//!           "device_type": null,
+//!           "cpe": [],
+//!           "payload": null,
+//!           "banner": null,
+//!           "tls": null
+//!         }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Every scanned port is included regardless of the ignored port states, `ports`
+//! is `null` for discovery-only scans, and hosts that are down are listed with
+//! an empty port list.
+//!
+//! A port's `service` is the one identified by version detection when it
+//! recognized the port, along with its `product`, `version`, `extra_info`, the
+//! `service_hostname`, `os` and `device_type` it revealed and its `cpe` names.
+//! Otherwise it is the name registered for the port number, with a
+//! `confidence` of 3.
+//!
+//! A port's `tls` object is set when TLS inspection completed a handshake with
+//! it. Its `upgrade` names the plaintext request, such as `STARTTLS`, that
+//! switched the connection to TLS first. Certificate validity dates are Unix
+//! timestamps in seconds, and `days_left` is negative for expired certificates:
+//!
+//! ```json
+//! "tls": {
+//!   "version": "TLSv1.3",
+//!   "cipher_suite": "TLS13_AES_256_GCM_SHA384",
+//!   "upgrade": null,
+//!   "alpn": "h2",
+//!   "certificate": {
+//!     "subject": "CN=example.com",
+//!     "issuer": "CN=R11, O=Let's Encrypt, C=US",
+//!     "sans": ["example.com", "www.example.com"],
+//!     "not_before": 1735689600,
+//!     "not_after": 1743465599,
+//!     "days_left": 42,
+//!     "self_signed": false,
+//!     "sha256": "3A:9F:...:C4"
+//!   }
+//! }
+//! ```
+use crate::results::{CertificateInfo, HostReport, HostStatus, ScanReport, ScanResult, TlsInfo};
+use serde::Serialize;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Version of the JSON document layout.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    format_version: u32,
+    scanner: &'static str,
+    scanner_version: &'static str,
+    command: &'a str,
+    /// Unix timestamp of the start of the scan, in seconds
+    start_time: u64,
+    duration_secs: f64,
+    /// True if the scan was cancelled and the hosts only hold the results so far
+    interrupted: bool,
+    ports: Option<&'a str>,
+    hosts_total: usize,
+    hosts_up: usize,
+    hosts: Vec<JsonHost<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonHost<'a> {
+    #[serde(flatten)]
+    host: HostInfo<'a>,
+    ports: Vec<JsonPort>,
+}
+
+/// The discovery outcome of a host, also used by the [`ndjson`](super::ndjson) events.
+#[derive(Serialize)]
+pub struct HostInfo<'a> {
+    target: String,
+    hostname: Option<&'a str>,
+    ip: String,
+    /// One of `up`, `assumed-up` or `down`
+    status: &'static str,
+    latency_ms: Option<f64>,
+    reason: Option<&'a str>,
+    mac: Option<String>,
+    vendor: Option<&'static str>,
+}
+
+/// A port result, also used by the [`ndjson`](super::ndjson) events.
+#[derive(Serialize)]
+pub struct JsonPort {
+    port: u16,
+    protocol: String,
+    state: String,
+    reason: &'static str,
+    rtt_ms: Option<f64>,
+    service: Option<String>,
+    /// From 0 to 10, 3 if the service was only looked up by port number
+    confidence: Option<u8>,
+    product: Option<String>,
+    version: Option<String>,
+    extra_info: Option<String>,
+    /// The hostname reported by the service, not the one the host was scanned by
+    service_hostname: Option<String>,
+    os: Option<String>,
+    device_type: Option<String>,
+    cpe: Vec<String>,
+    payload: Option<&'static str>,
+    banner: Option<String>,
+    tls: Option<JsonTls>,
+}
+
+#[derive(Serialize)]
+struct JsonTls {
+    version: String,
+    cipher_suite: String,
+    upgrade: Option<&'static str>,
+    alpn: Option<String>,
+    certificate: Option<JsonCertificate>,
+}
+
+#[derive(Serialize)]
+struct JsonCertificate {
+    subject: String,
+    issuer: String,
+    sans: Vec<String>,
+    /// Unix timestamps of the validity period, in seconds
+    not_before: i64,
+    not_after: i64,
+    /// Whole days until the certificate expires, negative once it has
+    days_left: i64,
+    self_signed: bool,
+    sha256: String,
+}
+
+impl<'a> From<&'a HostReport> for JsonHost<'a> {
+    fn from(host: &'a HostReport) -> Self {
+        JsonHost {
+            host: HostInfo::from(host),
+            ports: host.results.iter().map(JsonPort::from).collect(),
+        }
+    }
+}
+
+impl<'a> From<&'a HostReport> for HostInfo<'a> {
+    fn from(host: &'a HostReport) -> Self {
+        let reply = host.status.reply();
+        let mac = reply.and_then(|reply| reply.mac);
+        HostInfo {
+            target: host.target.to_string(),
+            hostname: host.target.hostname.as_deref(),
+            ip: host.target.ip.to_string(),
+            status: status_name(&host.status),
+            latency_ms: reply.map(|reply| reply.rtt.as_secs_f64() * 1000.0),
+            reason: reply.map(|reply| reply.reason.as_str()),
+            mac: mac.map(|mac| mac.to_string()),
+            vendor: mac.and_then(|mac| mac.vendor()),
+        }
+    }
+}
+
+impl From<&ScanResult> for JsonPort {
+    fn from(result: &ScanResult) -> Self {
+        let detected = result.service.as_ref();
+        JsonPort {
+            port: result.port,
+            protocol: result.protocol.to_string(),
+            state: result.state.to_string(),
+            reason: result.reason,
+            rtt_ms: result.rtt.map(|rtt| rtt.as_secs_f64() * 1000.0),
+            service: result.service_name().map(str::to_string),
+            confidence: result.service_confidence(),
+            product: detected.and_then(|service| service.product.clone()),
+            version: detected.and_then(|service| service.version.clone()),
+            extra_info: detected.and_then(|service| service.extra_info.clone()),
+            service_hostname: detected.and_then(|service| service.hostname.clone()),
+            os: detected.and_then(|service| service.os.clone()),
+            device_type: detected.and_then(|service| service.device_type.clone()),
+            cpe: detected
+                .map(|service| service.cpe.clone())
+                .unwrap_or_default(),
+            payload: result.payload,
+            banner: result.banner.clone(),
+            tls: result.tls.as_ref().map(JsonTls::from),
+        }
+    }
+}
+
+impl From<&TlsInfo> for JsonTls {
+    fn from(tls: &TlsInfo) -> Self {
+        JsonTls {
+            version: tls.version.clone(),
+            cipher_suite: tls.cipher_suite.clone(),
+            upgrade: tls.upgrade,
+            alpn: tls.alpn.clone(),
+            certificate: tls.certificate.as_ref().map(JsonCertificate::from),
+        }
+    }
+}
+
+impl From<&CertificateInfo> for JsonCertificate {
+    fn from(cert: &CertificateInfo) -> Self {
+        JsonCertificate {
+            subject: cert.subject.clone(),
+            issuer: cert.issuer.clone(),
+            sans: cert.sans.clone(),
+            not_before: unix_timestamp(cert.not_before),
+            not_after: unix_timestamp(cert.not_after),
+            days_left: cert.days_left(),
+            self_signed: cert.is_self_signed(),
+            sha256: cert.sha256.clone(),
+        }
+    }
+}
+
+/// Returns the seconds since the Unix epoch, negative for earlier times.
+fn unix_timestamp(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+/// Returns the JSON name of a host status, one of `up`, `assumed-up` or `down`.
+pub fn status_name(status: &HostStatus) -> &'static str {
+    match status {
+        HostStatus::Up(_) => "up",
+        HostStatus::Assumed => "assumed-up",
+        HostStatus::Down => "down",
+    }
+}
+
+/// Writes a scan report as a pretty printed JSON document.
+pub fn write_report(out: &mut dyn Write, report: &ScanReport) -> io::Result<()> {
+    let document = JsonReport {
+        format_version: FORMAT_VERSION,
+        scanner: env!("CARGO_PKG_NAME"),
+        scanner_version: env!("CARGO_PKG_VERSION"),
+        command: &report.command,
+        start_time: report
+            .start_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        duration_secs: report.duration.as_secs_f64(),
+        interrupted: report.interrupted,
+        ports: report.ports.as_deref(),
+        hosts_total: report.hosts.len(),
+        hosts_up: report.hosts_up(),
+        hosts: report.hosts.iter().map(JsonHost::from).collect(),
+    };
+
+    serde_json::to_writer_pretty(&mut *out, &document)?;
+    writeln!(out)
+}
@@ -0,0 +1,125 @@
+//! Scan report output formats.
+//!
+//! A finished [`ScanReport`] can be written in any of the supported
+//! [`OutputFormat`]s, to stdout or to a file:
+//!
+//! - [`text`] - The human readable report printed while scanning
+//! - [`json`] - A versioned JSON document for automation
+//! - [`xml`] - An nmap compatible XML document for tools that import nmap scans
+//! - [`grepable`] - One nmap style `Ports` line per host for shell pipelines
+//! - [`csv`] - One row per port for spreadsheets
+//! - [`ndjson`] - JSON events streamed while the scan runs
+use crate::results::{PortState, ScanReport};
+use clap::ValueEnum;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub mod csv;
+pub mod grepable;
+pub mod json;
+pub mod ndjson;
+pub mod text;
+pub mod xml;
+
+/// Specifies the format a scan report is written in.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text
+    Text,
+    /// Versioned JSON document
+    Json,
+    /// Nmap compatible XML document
+    Xml,
+    /// Nmap compatible grepable lines
+    Grepable,
+    /// Comma separated values, one row per port
+    Csv,
+    /// Newline-delimited JSON events, streamed while scanning
+    Ndjson,
+}
+
+impl OutputFormat {
+    /// Returns the file extension of reports in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Text => "txt",
+            OutputFormat::Json => "json",
+            OutputFormat::Xml => "xml",
+            OutputFormat::Grepable => "gnmap",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format = match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+            OutputFormat::Xml => "xml",
+            OutputFormat::Grepable => "grepable",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Ndjson => "ndjson",
+        };
+
+        write!(f, "{}", format)
+    }
+}
+
+/// Writes a scan report in the given format.
+///
+/// Ports in the `ignored` states are left out of the text and grepable reports,
+/// counted in `<extraports>` elements of the XML report, and kept in the JSON,
+/// CSV and NDJSON reports.
+pub fn write_report(
+    out: &mut dyn Write,
+    format: OutputFormat,
+    report: &ScanReport,
+    ignored: &[PortState],
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Text => text::write_report(out, report, ignored),
+        OutputFormat::Json => json::write_report(out, report),
+        OutputFormat::Xml => xml::write_report(out, report, ignored),
+        OutputFormat::Grepable => grepable::write_report(out, report, ignored),
+        OutputFormat::Csv => csv::write_report(out, report),
+        OutputFormat::Ndjson => ndjson::write_report(out, report),
+    }
+}
+
+/// Formats a time as an ISO 8601 timestamp in UTC, e.g. `2025-01-31T12:00:00Z`.
+pub fn format_utc(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    let (days, seconds) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+
+    // Converts days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = match month_index < 10 {
+        true => month_index + 3,
+        false => month_index - 9,
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
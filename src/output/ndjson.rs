@@ -0,0 +1,196 @@
+//! Newline-delimited JSON events.
+//!
+//! Unlike the other formats, NDJSON is written while the scan runs. Every event
+//! is a JSON object on its own line, flushed as soon as it happens, so other
+//! tools can act on results before the scan finishes. The `event` field names
+//! the kind of event:
+//!
+//! ```text
+//! {"event":"scan_started","format_version":1,"scanner":"pscan","scanner_version":"0.1.0","command":"pscan 10.0.0.44 -p 22,80 -o ndjson","start_time":1737590400,"ports":"22,80","hosts_total":1}
+//! {"event":"host_up","target":"10.0.0.44","hostname":null,"ip":"10.0.0.44","status":"up","latency_ms":0.4,"reason":"echo-reply","mac":null,"vendor":null}
+//! {"event":"port_result","ip":"10.0.0.44","port":80,"protocol":"tcp","state":"closed","reason":"conn-refused","rtt_ms":0.2,"service":"www-http","confidence":3,"product":null,"version":null,"extra_info":null,"service_hostname":null,"os":null,"device_type":null,"cpe":[],"payload":null,"banner":null,"tls":null}
+//! {"event":"port_result","ip":"10.0.0.44","port":22,"protocol":"tcp","state":"open","reason":"syn-ack","rtt_ms":0.3,"service":"ssh","confidence":3,"product":null,"version":null,"extra_info":null,"service_hostname":null,"os":null,"device_type":null,"cpe":[],"payload":null,"banner":null,"tls":null}
+//! {"event":"host_done","ip":"10.0.0.44","status":"up","ports_scanned":2,"ports_open":1}
+//! {"event":"scan_finished","duration_secs":0.012,"interrupted":false,"hosts_total":1,"hosts_up":1}
+//! ```
+//!
+//! Port results are written in the order their states are decided, not sorted
+//! by port. When a later stage such as banner grabbing learns more about a port,
+//! a `port_update` event repeats the port result with the new fields filled in.
+//! Hosts that are down only get a `host_done` event. A scan that is cancelled
+//! still ends with `scan_finished`, with `interrupted` set. The layout of the
+//! events is versioned by the `format_version` field of `scan_started`, under
+//! the same rules as the [`json`](super::json) report.
+use crate::output::json::{status_name, HostInfo, JsonPort};
+use crate::results::{HostReport, PortState, ScanReport, ScanResult};
+use crate::scanner::ScanObserver;
+use crate::scanners::Target;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+/// Version of the event layout.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    ScanStarted {
+        format_version: u32,
+        scanner: &'static str,
+        scanner_version: &'static str,
+        command: &'a str,
+        /// Unix timestamp of the start of the scan, in seconds
+        start_time: u64,
+        ports: Option<&'a str>,
+        hosts_total: usize,
+    },
+    HostUp(HostInfo<'a>),
+    PortResult {
+        ip: String,
+        #[serde(flatten)]
+        port: JsonPort,
+    },
+    PortUpdate {
+        ip: String,
+        #[serde(flatten)]
+        port: JsonPort,
+    },
+    HostDone {
+        ip: String,
+        status: &'static str,
+        ports_scanned: usize,
+        ports_open: usize,
+    },
+    ScanFinished {
+        duration_secs: f64,
+        interrupted: bool,
+        hosts_total: usize,
+        hosts_up: usize,
+    },
+}
+
+impl<'a> Event<'a> {
+    fn scan_started(report: &'a ScanReport, hosts_total: usize) -> Self {
+        Event::ScanStarted {
+            format_version: FORMAT_VERSION,
+            scanner: env!("CARGO_PKG_NAME"),
+            scanner_version: env!("CARGO_PKG_VERSION"),
+            command: &report.command,
+            start_time: report
+                .start_time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            ports: report.ports.as_deref(),
+            hosts_total,
+        }
+    }
+
+    fn port_result(target: &Target, result: &ScanResult) -> Self {
+        Event::PortResult {
+            ip: target.ip.to_string(),
+            port: JsonPort::from(result),
+        }
+    }
+
+    fn port_update(target: &Target, result: &ScanResult) -> Self {
+        Event::PortUpdate {
+            ip: target.ip.to_string(),
+            port: JsonPort::from(result),
+        }
+    }
+
+    fn host_done(host: &HostReport) -> Self {
+        Event::HostDone {
+            ip: host.target.ip.to_string(),
+            status: status_name(&host.status),
+            ports_scanned: host.results.len(),
+            ports_open: host
+                .results
+                .iter()
+                .filter(|r| r.state == PortState::Open)
+                .count(),
+        }
+    }
+
+    fn scan_finished(report: &ScanReport) -> Self {
+        Event::ScanFinished {
+            duration_secs: report.duration.as_secs_f64(),
+            interrupted: report.interrupted,
+            hosts_total: report.hosts.len(),
+            hosts_up: report.hosts_up(),
+        }
+    }
+}
+
+/// Writes one event as a line of JSON and flushes it.
+fn write_event(out: &mut dyn Write, event: &Event) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, event)?;
+    writeln!(out)?;
+    out.flush()
+}
+
+/// A [`ScanObserver`] streaming events to a writer while the scan runs.
+///
+/// The writer can be shared between scanner threads, each event is written as
+/// a whole line.
+pub struct EventWriter {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl EventWriter {
+    pub fn new(out: Box<dyn Write + Send>) -> Self {
+        EventWriter {
+            out: Mutex::new(out),
+        }
+    }
+
+    fn write(&self, event: &Event) -> io::Result<()> {
+        let mut out = self.out.lock().unwrap();
+        write_event(&mut *out, event)
+    }
+}
+
+impl ScanObserver for EventWriter {
+    fn scan_started(&self, report: &ScanReport, hosts_total: usize) -> io::Result<()> {
+        self.write(&Event::scan_started(report, hosts_total))
+    }
+
+    fn host_up(&self, host: &HostReport) -> io::Result<()> {
+        self.write(&Event::HostUp(HostInfo::from(host)))
+    }
+
+    fn port_result(&self, target: &Target, result: &ScanResult) -> io::Result<()> {
+        self.write(&Event::port_result(target, result))
+    }
+
+    fn port_updated(&self, target: &Target, result: &ScanResult) -> io::Result<()> {
+        self.write(&Event::port_update(target, result))
+    }
+
+    fn host_done(&self, host: &HostReport) -> io::Result<()> {
+        self.write(&Event::host_done(host))
+    }
+
+    fn scan_finished(&self, report: &ScanReport) -> io::Result<()> {
+        self.write(&Event::scan_finished(report))
+    }
+}
+
+/// Writes the events of a finished scan report at once, in the order they
+/// would have been streamed with port results sorted.
+pub fn write_report(out: &mut dyn Write, report: &ScanReport) -> io::Result<()> {
+    write_event(out, &Event::scan_started(report, report.hosts.len()))?;
+    for host in &report.hosts {
+        if host.status.is_up() {
+            write_event(out, &Event::HostUp(HostInfo::from(host)))?;
+        }
+        for result in &host.results {
+            write_event(out, &Event::port_result(&host.target, result))?;
+        }
+        write_event(out, &Event::host_done(host))?;
+    }
+    write_event(out, &Event::scan_finished(report))
+}
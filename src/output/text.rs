@@ -0,0 +1,348 @@
+//! Human readable text output.
+//!
+//! This is the default output format. Each host that is up gets a report with
+//! its discovery result and a table of port states, and the scan ends with a
+//! one line summary:
+//!
+//! ```text
+//! Host is up (0ms latency, received echo-reply).
+//! pscan report for 10.0.0.44:54000-60000
+//! Not shown: 6000 closed ports
+//! PORT       STATE         SERVICE
+//! 54446/tcp  open          unknown
+//!
+//! pscan done: 1 IP address (1 host up) scanned in 0.08 seconds
+//! ```
+use crate::output::format_utc;
+use crate::results::{
+    CertificateInfo, HostReport, HostStatus, PortState, ScanReport, ScanResult, TlsInfo,
+};
+use crate::scanners::neighbor::MacAddr;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+/// Writes a complete scan report.
+pub fn write_report(
+    out: &mut dyn Write,
+    report: &ScanReport,
+    ignored: &[PortState],
+) -> io::Result<()> {
+    match report.ports {
+        Some(ref ports) => {
+            for host in &report.hosts {
+                write_host(out, host, ports, ignored)?;
+            }
+        }
+        None => {
+            write_live_hosts(out, &report.hosts)?;
+            writeln!(out)?;
+        }
+    }
+
+    write_summary(out, report)
+}
+
+/// Writes the discovery result and port table of a single host.
+///
+/// # Output Format
+///
+/// The output includes:
+/// 1. A line telling how the host was found to be up
+/// 2. A header showing the target address and port range, followed by the
+///    host's MAC address and vendor if it was resolved on the local network
+/// 3. Summary of ignored ports by state (if any)
+/// 4. Table of discovered ports with their states and services, plus the
+///    payload that got a reply when any port answered a protocol payload, the
+///    product and version when version detection identified any service, and
+///    each port's banner and TLS handshake on `|` lines below its row
+/// 5. A `Service Info:` line with the hostnames, operating systems, device
+///    types and platform CPE names the detected services revealed (if any)
+///
+/// Services identified by version detection replace the name registered for
+/// the port number.
+///
+/// Nothing is written for hosts that are down.
+pub fn write_host(
+    out: &mut dyn Write,
+    host: &HostReport,
+    ports: &str,
+    ignored: &[PortState],
+) -> io::Result<()> {
+    match host.status {
+        HostStatus::Up(ref reply) => writeln!(
+            out,
+            "Host is up ({}ms latency, received {}).",
+            reply.rtt.as_millis(),
+            reply.reason
+        )?,
+        HostStatus::Assumed => writeln!(
+            out,
+            "Host discovery skipped, assuming {} is up.",
+            host.target
+        )?,
+        HostStatus::Down => return Ok(()),
+    }
+
+    writeln!(out, "pscan report for {}:{}", host.target, ports)?;
+    if let Some(mac) = host.status.reply().and_then(|reply| reply.mac.as_ref()) {
+        writeln!(out, "MAC Address: {}", format_mac(mac))?;
+    }
+
+    for state in ignored {
+        let ignored_cnt = host.results.iter().filter(|r| r.state == *state).count();
+        if ignored_cnt > 0 {
+            writeln!(out, "Not shown: {} {} ports", ignored_cnt, state)?;
+        }
+    }
+
+    let results = host
+        .results
+        .iter()
+        .filter(|r| !ignored.contains(&r.state))
+        .collect::<Vec<_>>();
+
+    let show_payload = results.iter().any(|r| r.payload.is_some());
+    let show_version = results.iter().any(|r| r.service.is_some());
+    let write_row = |out: &mut dyn Write,
+                     port: &str,
+                     state: &str,
+                     service: &str,
+                     payload: &str,
+                     version: &str| {
+        match show_payload || show_version {
+            true => write!(out, "{:<10} {:<13} {:<15}", port, state, service)?,
+            false => write!(out, "{:<10} {:<13} {:<10}", port, state, service)?,
+        }
+        if show_payload {
+            write!(out, " {:<10}", payload)?;
+        }
+        if show_version {
+            write!(out, " {}", version)?;
+        }
+        writeln!(out)
+    };
+
+    write_row(out, "PORT", "STATE", "SERVICE", "PAYLOAD", "VERSION")?;
+    for result in &results {
+        let version = result
+            .service
+            .as_ref()
+            .and_then(|service| service.version_line())
+            .unwrap_or_default();
+        write_row(
+            out,
+            &format!("{}/{}", result.port, result.protocol),
+            &result.state.to_string(),
+            result.service_name().unwrap_or("unknown"),
+            result.payload.unwrap_or("-"),
+            &version,
+        )?;
+
+        let mut details = Vec::new();
+        if let Some(ref banner) = result.banner {
+            details.push(format!("banner: {}", banner));
+        }
+        if let Some(ref tls) = result.tls {
+            details.push(format!("tls: {}", tls_summary(tls)));
+            if let Some(ref cert) = tls.certificate {
+                let mut lines = certificate_lines(cert).into_iter();
+                details.extend(lines.next().map(|line| format!("ssl-cert: {}", line)));
+                details.extend(lines);
+            }
+        }
+        for (i, line) in details.iter().enumerate() {
+            match i + 1 == details.len() {
+                true => writeln!(out, "|_{}", line)?,
+                false => writeln!(out, "| {}", line)?,
+            }
+        }
+    }
+    write_service_info(out, &results)?;
+
+    writeln!(out)
+}
+
+/// Writes what the detected services revealed about their host, e.g.
+/// `Service Info: Host: mail.example.com; OS: Linux; CPE: cpe:/o:linux:linux_kernel`.
+///
+/// Only the CPE names of operating systems and hardware are listed, those of
+/// the products are specific to their port.
+fn write_service_info(out: &mut dyn Write, results: &[&ScanResult]) -> io::Result<()> {
+    let services: Vec<_> = results.iter().filter_map(|r| r.service.as_ref()).collect();
+    let fields = [
+        (
+            "Host",
+            join_unique(services.iter().filter_map(|s| s.hostname.as_ref())),
+        ),
+        (
+            "OS",
+            join_unique(services.iter().filter_map(|s| s.os.as_ref())),
+        ),
+        (
+            "Device",
+            join_unique(services.iter().filter_map(|s| s.device_type.as_ref())),
+        ),
+        (
+            "CPE",
+            join_unique(
+                services
+                    .iter()
+                    .flat_map(|s| &s.cpe)
+                    .filter(|cpe| cpe.starts_with("cpe:/o") || cpe.starts_with("cpe:/h")),
+            ),
+        ),
+    ];
+    let info: Vec<String> = fields
+        .iter()
+        .filter(|(_, values)| !values.is_empty())
+        .map(|(name, values)| format!("{}: {}", name, values))
+        .collect();
+    if !info.is_empty() {
+        writeln!(out, "Service Info: {}", info.join("; "))?;
+    }
+
+    Ok(())
+}
+
+/// Describes a TLS handshake in one line, e.g.
+/// `TLSv1.3 TLS13_AES_256_GCM_SHA384 via STARTTLS (ALPN: h2)`.
+pub fn tls_summary(tls: &TlsInfo) -> String {
+    let mut summary = format!("{} {}", tls.version, tls.cipher_suite);
+    if let Some(upgrade) = tls.upgrade {
+        let _ = write!(summary, " via {}", upgrade);
+    }
+    if let Some(ref alpn) = tls.alpn {
+        let _ = write!(summary, " (ALPN: {})", alpn);
+    }
+
+    summary
+}
+
+/// Describes a certificate in the lines of nmap's `ssl-cert` script, noting
+/// self-signed certificates and how long until the certificate expires.
+pub fn certificate_lines(cert: &CertificateInfo) -> Vec<String> {
+    let mut lines = vec![format!("Subject: {}", cert.subject)];
+    if !cert.sans.is_empty() {
+        lines.push(format!(
+            "Subject Alternative Name: {}",
+            cert.sans.join(", ")
+        ));
+    }
+    match cert.is_self_signed() {
+        true => lines.push(format!("Issuer: {} (self-signed)", cert.issuer)),
+        false => lines.push(format!("Issuer: {}", cert.issuer)),
+    }
+    lines.push(format!("Not valid before: {}", format_utc(cert.not_before)));
+
+    let days_left = cert.days_left();
+    let expiry = match days_left {
+        0.. => format!("expires in {}", days(days_left)),
+        _ => format!("expired {} ago", days(-days_left)),
+    };
+    lines.push(format!(
+        "Not valid after:  {} ({})",
+        format_utc(cert.not_after),
+        expiry
+    ));
+    lines.push(format!("SHA-256: {}", cert.sha256));
+
+    lines
+}
+
+/// Formats a number of days, e.g. `1 day` or `30 days`.
+fn days(n: i64) -> String {
+    match n {
+        1 => "1 day".to_string(),
+        _ => format!("{} days", n),
+    }
+}
+
+/// Joins values with commas, leaving out repeated ones.
+fn join_unique<'a>(values: impl Iterator<Item = &'a String>) -> String {
+    let mut unique: Vec<&str> = Vec::new();
+    for value in values {
+        if !unique.contains(&value.as_str()) {
+            unique.push(value);
+        }
+    }
+
+    unique.join(", ")
+}
+
+/// Formats a MAC address followed by its vendor, e.g. `B8:27:EB:12:34:56 (Raspberry Pi Foundation)`.
+fn format_mac(mac: &MacAddr) -> String {
+    format!("{} ({})", mac, mac.vendor().unwrap_or("Unknown"))
+}
+
+/// Writes the hosts found alive by a discovery-only scan.
+///
+/// Each host is listed with its discovery latency and the probe answer that
+/// showed it is up, plus its MAC address and vendor when any host was resolved
+/// on the local network.
+pub fn write_live_hosts(out: &mut dyn Write, hosts: &[HostReport]) -> io::Result<()> {
+    let hosts: Vec<_> = hosts
+        .iter()
+        .filter_map(|host| host.status.reply().map(|reply| (&host.target, reply)))
+        .collect();
+    let width = hosts
+        .iter()
+        .map(|(target, _)| target.to_string().len())
+        .max()
+        .unwrap_or_default()
+        .max(4);
+    let show_mac = hosts.iter().any(|(_, reply)| reply.mac.is_some());
+
+    match show_mac {
+        true => writeln!(
+            out,
+            "{:<width$} {:<10} {:<15} MAC ADDRESS",
+            "HOST", "LATENCY", "REASON"
+        )?,
+        false => writeln!(out, "{:<width$} {:<10} REASON", "HOST", "LATENCY")?,
+    }
+    for (target, reply) in hosts {
+        let latency = format!("{}ms", reply.rtt.as_millis());
+        match show_mac {
+            true => writeln!(
+                out,
+                "{:<width$} {:<10} {:<15} {}",
+                target.to_string(),
+                latency,
+                reply.reason,
+                reply
+                    .mac
+                    .as_ref()
+                    .map(format_mac)
+                    .unwrap_or_else(|| "-".to_string())
+            )?,
+            false => writeln!(
+                out,
+                "{:<width$} {:<10} {}",
+                target.to_string(),
+                latency,
+                reply.reason
+            )?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the footer summarizing how many hosts were scanned and the total scan duration.
+pub fn write_summary(out: &mut dyn Write, report: &ScanReport) -> io::Result<()> {
+    let n_targets = report.hosts.len();
+    let n_up = report.hosts_up();
+    writeln!(
+        out,
+        "pscan {}: {} IP address{} ({} host{} up) scanned in {:.2} seconds",
+        match report.interrupted {
+            true => "interrupted",
+            false => "done",
+        },
+        n_targets,
+        if n_targets == 1 { "" } else { "es" },
+        n_up,
+        if n_up == 1 { "" } else { "s" },
+        report.duration.as_secs_f64()
+    )
+}
@@ -0,0 +1,275 @@
+//! Nmap compatible XML output.
+//!
+//! The report follows the layout of nmap's `nmap.dtd` (`xmloutputversion`
+//! 1.05), so tools that import nmap XML or render it with XSLT report styles
+//! can read pscan scans as well:
+//!
+//! ```xml
+//! <nmaprun scanner="pscan" args="pscan 10.0.0.44 -p 22,80" start="1737590400" version="0.1.0" xmloutputversion="1.05">
+//! <scaninfo type="connect" protocol="tcp" numservices="2" services="22,80"/>
+//! <host><status state="up" reason="echo-reply" reason_ttl="0"/>
+//! <address addr="10.0.0.44" addrtype="ipv4"/>
+//! <ports>
+//! <port protocol="tcp" portid="22"><state state="open" reason="syn-ack" reason_ttl="0"/><service name="ssh" method="table" conf="3"/></port>
+//! ...
+//! ```
+//!
+//! Ports in the ignored states are summarized in `<extraports>` elements like
+//! nmap does for the states it does not list. Banners are written as the output
+//! of a `banner` script, like nmap's banner NSE script reports them. TLS
+//! handshakes are written as a `tls` script, and certificates as an `ssl-cert`
+//! script with the `validity` table and `sha256` element nmap's script has. An
+//! interrupted scan finishes with `exit="error"`, as nmap does when it is aborted.
+use crate::output::{format_utc, text};
+use crate::results::{
+    service_name, HostReport, HostStatus, PortState, ScanReport, ScanResult, TABLE_CONFIDENCE,
+};
+use crate::scanners::ports::format_ports;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::time::UNIX_EPOCH;
+
+/// Version of the nmap XML layout the output follows.
+pub const XML_OUTPUT_VERSION: &str = "1.05";
+
+/// Writes a scan report as an nmap XML document.
+pub fn write_report(
+    out: &mut dyn Write,
+    report: &ScanReport,
+    ignored: &[PortState],
+) -> io::Result<()> {
+    let start = unix_time(report.start_time);
+    let end = unix_time(report.start_time + report.duration);
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, "<!DOCTYPE nmaprun>")?;
+    writeln!(
+        out,
+        r#"<nmaprun scanner="{}" args="{}" start="{}" version="{}" xmloutputversion="{}">"#,
+        env!("CARGO_PKG_NAME"),
+        escape(&report.command),
+        start,
+        env!("CARGO_PKG_VERSION"),
+        XML_OUTPUT_VERSION
+    )?;
+    for scan in &report.scans {
+        writeln!(
+            out,
+            r#"<scaninfo type="{}" protocol="{}" numservices="{}" services="{}"/>"#,
+            scan.scan_type,
+            scan.protocol,
+            scan.ports.len(),
+            format_ports(&scan.ports)
+        )?;
+    }
+    writeln!(out, r#"<verbose level="0"/>"#)?;
+    writeln!(out, r#"<debugging level="0"/>"#)?;
+
+    for host in &report.hosts {
+        write_host(out, host, report.ports.is_some(), ignored)?;
+    }
+
+    let n_up = report.hosts_up();
+    let n_down = report.hosts.len() - n_up;
+    let exit = match report.interrupted {
+        true => r#"exit="error" errormsg="Interrupted""#,
+        false => r#"exit="success""#,
+    };
+    writeln!(out, "<runstats>")?;
+    writeln!(
+        out,
+        r#"<finished time="{}" elapsed="{:.2}" summary="pscan done; {} IP address{} ({} host{} up) scanned in {:.2} seconds" {}/>"#,
+        end,
+        report.duration.as_secs_f64(),
+        report.hosts.len(),
+        if report.hosts.len() == 1 { "" } else { "es" },
+        n_up,
+        if n_up == 1 { "" } else { "s" },
+        report.duration.as_secs_f64(),
+        exit
+    )?;
+    writeln!(
+        out,
+        r#"<hosts up="{}" down="{}" total="{}"/>"#,
+        n_up,
+        n_down,
+        report.hosts.len()
+    )?;
+    writeln!(out, "</runstats>")?;
+    writeln!(out, "</nmaprun>")
+}
+
+/// Writes the `<host>` element of one target.
+fn write_host(
+    out: &mut dyn Write,
+    host: &HostReport,
+    ports_scanned: bool,
+    ignored: &[PortState],
+) -> io::Result<()> {
+    let (state, reason) = match host.status {
+        HostStatus::Up(ref reply) => ("up", reply.reason.as_str()),
+        HostStatus::Assumed => ("up", "user-set"),
+        HostStatus::Down => ("down", "no-response"),
+    };
+    // Discovery reasons name the port they came from, nmap only names the kind of reply.
+    let reason = reason.split(" on ").next().unwrap_or(reason);
+
+    write!(out, "<host>")?;
+    writeln!(
+        out,
+        r#"<status state="{}" reason="{}" reason_ttl="0"/>"#,
+        state,
+        escape(reason)
+    )?;
+    let addrtype = match host.target.ip {
+        IpAddr::V4(_) => "ipv4",
+        IpAddr::V6(_) => "ipv6",
+    };
+    writeln!(
+        out,
+        r#"<address addr="{}" addrtype="{}"/>"#,
+        host.target.ip, addrtype
+    )?;
+    if let Some(mac) = host.status.reply().and_then(|reply| reply.mac) {
+        match mac.vendor() {
+            Some(vendor) => writeln!(
+                out,
+                r#"<address addr="{}" addrtype="mac" vendor="{}"/>"#,
+                mac,
+                escape(vendor)
+            )?,
+            None => writeln!(out, r#"<address addr="{}" addrtype="mac"/>"#, mac)?,
+        }
+    }
+    match host.target.hostname {
+        Some(ref hostname) => writeln!(
+            out,
+            r#"<hostnames><hostname name="{}" type="user"/></hostnames>"#,
+            escape(hostname)
+        )?,
+        None => writeln!(out, "<hostnames></hostnames>")?,
+    }
+
+    if ports_scanned && host.status.is_up() {
+        writeln!(out, "<ports>")?;
+        for state in ignored {
+            let count = host.results.iter().filter(|r| r.state == *state).count();
+            if count > 0 {
+                writeln!(
+                    out,
+                    r#"<extraports state="{}" count="{}"></extraports>"#,
+                    state, count
+                )?;
+            }
+        }
+        for result in host.results.iter().filter(|r| !ignored.contains(&r.state)) {
+            write_port(out, result)?;
+        }
+        writeln!(out, "</ports>")?;
+    }
+
+    if let Some(reply) = host.status.reply() {
+        let srtt = reply.rtt.as_micros();
+        writeln!(
+            out,
+            r#"<times srtt="{}" rttvar="{}" to="{}"/>"#,
+            srtt,
+            srtt / 2,
+            (srtt * 3).max(100_000)
+        )?;
+    }
+    writeln!(out, "</host>")
+}
+
+/// Writes the `<port>` element of one scanned port.
+fn write_port(out: &mut dyn Write, result: &ScanResult) -> io::Result<()> {
+    write!(
+        out,
+        r#"<port protocol="{}" portid="{}"><state state="{}" reason="{}" reason_ttl="0"/>"#,
+        result.protocol, result.port, result.state, result.reason
+    )?;
+    match result.service {
+        Some(ref service) => {
+            write!(out, r#"<service name="{}""#, escape(&service.name))?;
+            let fields = [
+                ("product", &service.product),
+                ("version", &service.version),
+                ("extrainfo", &service.extra_info),
+                ("ostype", &service.os),
+                ("hostname", &service.hostname),
+                ("devicetype", &service.device_type),
+            ];
+            for (name, value) in fields {
+                if let Some(value) = value {
+                    write!(out, r#" {}="{}""#, name, escape(value))?;
+                }
+            }
+            write!(out, r#" method="probed" conf="{}""#, service.confidence)?;
+            match service.cpe.is_empty() {
+                true => write!(out, "/>")?,
+                false => {
+                    write!(out, ">")?;
+                    for cpe in &service.cpe {
+                        write!(out, "<cpe>{}</cpe>", escape(cpe))?;
+                    }
+                    write!(out, "</service>")?;
+                }
+            }
+        }
+        None => {
+            if let Some(service) = service_name(&result.protocol, result.port) {
+                write!(
+                    out,
+                    r#"<service name="{}" method="table" conf="{}"/>"#,
+                    escape(service),
+                    TABLE_CONFIDENCE
+                )?;
+            }
+        }
+    }
+    if let Some(ref banner) = result.banner {
+        write!(out, r#"<script id="banner" output="{}"/>"#, escape(banner))?;
+    }
+    if let Some(ref tls) = result.tls {
+        write!(
+            out,
+            r#"<script id="tls" output="{}"/>"#,
+            escape(&text::tls_summary(tls))
+        )?;
+        if let Some(ref cert) = tls.certificate {
+            write!(
+                out,
+                r#"<script id="ssl-cert" output="{}"><table key="validity"><elem key="notBefore">{}</elem><elem key="notAfter">{}</elem></table><elem key="sha256">{}</elem></script>"#,
+                escape(&text::certificate_lines(cert).join("\n")),
+                format_utc(cert.not_before),
+                format_utc(cert.not_after),
+                cert.sha256
+            )?;
+        }
+    }
+    writeln!(out, "</port>")
+}
+
+/// Returns seconds since the Unix epoch.
+fn unix_time(time: std::time::SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Escapes the characters that are not allowed verbatim in XML attribute values.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#xa;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -5,14 +5,16 @@
 //! - Storing individual scan results (`ScanResult`)
 //! - Managing collections of scan results (`ScanResults`)
 //! - Mapping port numbers to service names using IANA registries
-//! - Formatting and displaying scan results
-use crate::scanners::{Address, ScanProtocol};
-use crate::utils;
-use crate::Args;
+//! - Describing services identified by version detection (`ServiceInfo`)
+//! - Collecting the per-host outcome of a scan into a report (`ScanReport`),
+//!   which the [`output`](crate::output) formats write out
+use crate::scanners::discovery::PingReply;
+use crate::scanners::{ScanProtocol, Target};
 use clap::ValueEnum;
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::time::{Duration, SystemTime};
 
 /// Represents the state of a port after scanning.
 #[derive(Debug, PartialEq, Clone, ValueEnum)]
@@ -23,6 +25,11 @@ pub enum PortState {
     Closed,
     /// Port's state could not be determined (possibly due to firewall)
     Filtered,
+    /// Port is reachable through any firewall but may be open or closed
+    Unfiltered,
+    /// Port did not answer, it is either open or filtered
+    #[value(name = "open|filtered", alias = "open-filtered")]
+    OpenFiltered,
 }
 
 impl Display for PortState {
@@ -31,6 +38,8 @@ impl Display for PortState {
             PortState::Open => "open",
             PortState::Closed => "closed",
             PortState::Filtered => "filtered",
+            PortState::Unfiltered => "unfiltered",
+            PortState::OpenFiltered => "open|filtered",
         };
 
         write!(f, "{}", state)
@@ -46,16 +55,160 @@ pub struct ScanResult {
     pub port: u16,
     /// The state of the port after scanning (Open, Closed, or Filtered)
     pub state: PortState,
+    /// The kind of reply the state was derived from, e.g. `syn-ack` or `no-response`
+    pub reason: &'static str,
+    /// The round trip time of the answered probe, `None` if no probe was answered
+    pub rtt: Option<Duration>,
+    /// The name of the protocol payload that got a reply, if any
+    pub payload: Option<&'static str>,
+    /// The sanitized greeting of the service, if banners were grabbed and it sent one
+    pub banner: Option<String>,
+    /// The service identified by version detection, if it ran and recognized one
+    pub service: Option<ServiceInfo>,
+    /// What a TLS handshake revealed, if TLS inspection ran and the port speaks TLS
+    pub tls: Option<TlsInfo>,
 }
 
 impl ScanResult {
-    pub fn new(protocol: ScanProtocol, port: u16, state: PortState) -> Self {
+    pub fn new(protocol: ScanProtocol, port: u16, state: PortState, reason: &'static str) -> Self {
         ScanResult {
             protocol,
             port,
             state,
+            reason,
+            rtt: None,
+            payload: None,
+            banner: None,
+            service: None,
+            tls: None,
         }
     }
+
+    /// Records the round trip time of the probe that was answered.
+    pub fn with_rtt(mut self, rtt: Duration) -> Self {
+        self.rtt = Some(rtt);
+        self
+    }
+
+    /// Records the name of the protocol payload the port replied to.
+    pub fn with_payload(mut self, payload: &'static str) -> Self {
+        self.payload = Some(payload);
+        self
+    }
+
+    /// Returns the name of the service on the port: the one identified by
+    /// version detection, or else the IANA registered name of the port.
+    pub fn service_name(&self) -> Option<&str> {
+        match self.service {
+            Some(ref service) => Some(&service.name),
+            None => service_name(&self.protocol, self.port),
+        }
+    }
+
+    /// Returns how sure the service name is, from 0 to 10 like nmap's `conf`.
+    ///
+    /// Names guessed from the port number get [`TABLE_CONFIDENCE`].
+    pub fn service_confidence(&self) -> Option<u8> {
+        match self.service {
+            Some(ref service) => Some(service.confidence),
+            None => service_name(&self.protocol, self.port).map(|_| TABLE_CONFIDENCE),
+        }
+    }
+}
+
+/// Confidence of a service name looked up by port number.
+pub const TABLE_CONFIDENCE: u8 = 3;
+
+/// A service identified by version detection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceInfo {
+    /// The service name, e.g. `ssh` or `http`
+    pub name: String,
+    /// The product implementing the service, e.g. `OpenSSH`
+    pub product: Option<String>,
+    /// The product version
+    pub version: Option<String>,
+    /// Anything else learned, such as the protocol version
+    pub extra_info: Option<String>,
+    /// The hostname the service gave for its host
+    pub hostname: Option<String>,
+    /// The operating system the service runs on, e.g. `Linux`
+    pub os: Option<String>,
+    /// The kind of device running the service, e.g. `router` or `printer`
+    pub device_type: Option<String>,
+    /// CPE names of the product and platform, e.g. `cpe:/a:openbsd:openssh:9.6p1`
+    pub cpe: Vec<String>,
+    /// How sure the detection is, from 0 to 10: 10 for a full match and less
+    /// for a match that only names the service
+    pub confidence: u8,
+}
+
+impl ServiceInfo {
+    /// Returns the product, version and extra info in one line, e.g.
+    /// `OpenSSH 9.6p1 (protocol 2.0)`, or `None` if none of them is known.
+    pub fn version_line(&self) -> Option<String> {
+        let mut parts: Vec<String> = [&self.product, &self.version]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        if let Some(ref info) = self.extra_info {
+            parts.push(format!("({})", info));
+        }
+
+        (!parts.is_empty()).then(|| parts.join(" "))
+    }
+}
+
+/// The outcome of a TLS handshake with a port.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsInfo {
+    /// The negotiated protocol version, e.g. `TLSv1.3`
+    pub version: String,
+    /// The plaintext request that switched the connection to TLS, e.g.
+    /// `STARTTLS`, or `None` if the port spoke TLS right away
+    pub upgrade: Option<&'static str>,
+    /// The negotiated cipher suite, e.g. `TLS13_AES_256_GCM_SHA384`
+    pub cipher_suite: String,
+    /// The application protocol agreed on through ALPN, e.g. `h2`
+    pub alpn: Option<String>,
+    /// The server's own certificate, the first of the chain it sent
+    pub certificate: Option<CertificateInfo>,
+}
+
+/// The details of an X.509 certificate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertificateInfo {
+    /// The subject's distinguished name, e.g. `CN=example.com, O=Example`
+    pub subject: String,
+    /// The issuer's distinguished name
+    pub issuer: String,
+    /// The DNS names and IP addresses of the subject alternative name extension
+    pub sans: Vec<String>,
+    /// Start of the validity period
+    pub not_before: SystemTime,
+    /// End of the validity period
+    pub not_after: SystemTime,
+    /// SHA-256 fingerprint of the DER encoding, as colon separated hex bytes
+    pub sha256: String,
+}
+
+impl CertificateInfo {
+    /// Returns true if the certificate names itself as its issuer.
+    pub fn is_self_signed(&self) -> bool {
+        self.subject == self.issuer
+    }
+
+    /// Returns the whole days left until the certificate expires, negative
+    /// once it has expired.
+    pub fn days_left(&self) -> i64 {
+        let seconds = match self.not_after.duration_since(SystemTime::now()) {
+            Ok(left) => left.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        };
+
+        seconds.div_euclid(86400)
+    }
 }
 
 /// A collection of scan results representing the outcome of port scanning operations.
@@ -63,7 +216,7 @@ pub type ScanResults = Vec<ScanResult>;
 
 /// Static mapping of TCP port numbers to their corresponding IANA registered service names.
 /// This mapping is lazily initialized from the embedded CSV file containing IANA TCP service definitions.
-static TCP_SERVICES: Lazy<HashMap<u16, &str>> = Lazy::new(|| {
+static TCP_SERVICES: Lazy<HashMap<u16, &'static str>> = Lazy::new(|| {
     let tcp_services = include_str!("../services/iana_tcp_services.csv");
     let map = tcp_services
         .lines()
@@ -80,7 +233,7 @@ static TCP_SERVICES: Lazy<HashMap<u16, &str>> = Lazy::new(|| {
 
 /// Static mapping of UDP port numbers to their corresponding IANA registered service names.
 /// This mapping is lazily initialized from the embedded CSV file containing IANA UDP service definitions.
-static UDP_SERVICES: Lazy<HashMap<u16, &str>> = Lazy::new(|| {
+static UDP_SERVICES: Lazy<HashMap<u16, &'static str>> = Lazy::new(|| {
     let tcp_services = include_str!("../services/iana_udp_services.csv");
     let map = tcp_services
         .lines()
@@ -95,63 +248,125 @@ static UDP_SERVICES: Lazy<HashMap<u16, &str>> = Lazy::new(|| {
     map
 });
 
-/// Prints the formatted results of a port scanning operation.
-///
-/// # Output Format
-///
-/// The output includes:
-/// 1. A header showing the target address and port range
-/// 2. Summary of ignored ports by state (if any)
-/// 3. Table of discovered ports with their states and services
-/// 4. Footer showing total scan duration
-pub fn print_results(args: &Args, results: ScanResults, duration: std::time::Duration) {
-    match args.addr {
-        Address::Ip(ip) => {
-            println!("pscan report for {}:{}", ip, args.port_range);
-        }
-        Address::Hostname(ref hostname) => match utils::resolve_hostname_to_ip(hostname) {
-            Some(ip) => {
-                println!("pscan report for {} ({}):{}", hostname, ip, args.port_range);
-            }
-            None => {
-                println!(
-                    "pscan report for {} (unknown):{}",
-                    hostname, args.port_range
-                );
-            }
-        },
+/// Static mapping of lowercased IANA TCP service names to every port registered under that name.
+static TCP_SERVICE_PORTS: Lazy<HashMap<String, Vec<u16>>> = Lazy::new(|| {
+    let tcp_services = include_str!("../services/iana_tcp_services.csv");
+    tcp_services
+        .lines()
+        .skip(1)
+        .fold(HashMap::new(), |mut acc, line| {
+            let mut parts = line.split(',');
+            let service = parts.next().unwrap().to_ascii_lowercase();
+            let port = parts.next().unwrap().parse::<u16>().unwrap();
+            acc.entry(service).or_insert_with(Vec::new).push(port);
+            acc
+        })
+});
+
+/// Static mapping of lowercased IANA UDP service names to every port registered under that name.
+static UDP_SERVICE_PORTS: Lazy<HashMap<String, Vec<u16>>> = Lazy::new(|| {
+    let udp_services = include_str!("../services/iana_udp_services.csv");
+    udp_services
+        .lines()
+        .skip(1)
+        .fold(HashMap::new(), |mut acc, line| {
+            let mut parts = line.split(',');
+            let service = parts.next().unwrap().to_ascii_lowercase();
+            let port = parts.next().unwrap().parse::<u16>().unwrap();
+            acc.entry(service).or_insert_with(Vec::new).push(port);
+            acc
+        })
+});
+
+/// Returns the IANA registered service name for a port, if any.
+pub fn service_name(protocol: &ScanProtocol, port: u16) -> Option<&'static str> {
+    match protocol {
+        ScanProtocol::Tcp => TCP_SERVICES.get(&port),
+        ScanProtocol::Udp => UDP_SERVICES.get(&port),
     }
+    .copied()
+}
 
-    for state in &args.ignored_state {
-        let ignored_cnt = results.iter().filter(|r| r.state == *state).count();
-        if ignored_cnt > 0 {
-            println!("Not shown: {} {} ports", ignored_cnt, state);
-        }
+/// Returns the ports registered for a service name, ignoring case.
+pub fn service_ports(protocol: &ScanProtocol, name: &str) -> Vec<u16> {
+    match protocol {
+        ScanProtocol::Tcp => TCP_SERVICE_PORTS.get(&name.to_ascii_lowercase()),
+        ScanProtocol::Udp => UDP_SERVICE_PORTS.get(&name.to_ascii_lowercase()),
     }
+    .cloned()
+    .unwrap_or_default()
+}
+
+/// The outcome of host discovery for one target.
+#[derive(Debug, Clone)]
+pub enum HostStatus {
+    /// The host answered a discovery probe
+    Up(PingReply),
+    /// Discovery was skipped and the host was scanned regardless
+    Assumed,
+    /// No discovery probe was answered, the host was not scanned
+    Down,
+}
 
-    let results = results
-        .into_iter()
-        .filter(|r| !args.ignored_state.contains(&r.state))
-        .collect::<Vec<_>>();
+impl HostStatus {
+    /// Returns true if the host was scanned.
+    pub fn is_up(&self) -> bool {
+        !matches!(self, HostStatus::Down)
+    }
 
-    println!("{:<10} {:<10} {:<10}", "PORT", "STATE", "SERVICE");
-    for result in results {
-        let service = match result.protocol {
-            ScanProtocol::Tcp => TCP_SERVICES.get(&result.port),
-            ScanProtocol::Udp => UDP_SERVICES.get(&result.port),
+    /// Returns the discovery reply if the host answered one.
+    pub fn reply(&self) -> Option<&PingReply> {
+        match self {
+            HostStatus::Up(reply) => Some(reply),
+            _ => None,
         }
-        .unwrap_or(&"unknown");
-
-        println!(
-            "{:<10} {:<10} {:<10}",
-            format!("{}/{}", result.port, result.protocol),
-            format!("{}", result.state),
-            service
-        );
     }
+}
+
+/// Everything learned about a single target.
+#[derive(Debug)]
+pub struct HostReport {
+    /// The scanned host
+    pub target: Target,
+    /// Whether and how the host was found to be up
+    pub status: HostStatus,
+    /// The state of every scanned port, empty if the host is down or ports were not scanned
+    pub results: ScanResults,
+}
 
-    println!(
-        "\npscan done: scanned in {:.2} seconds",
-        duration.as_secs_f64()
-    );
+/// One kind of port scan run against every host that is up.
+#[derive(Debug)]
+pub struct ScanInfo {
+    /// The scan technique, e.g. `connect`, `syn` or `udp`
+    pub scan_type: String,
+    /// The protocol of the scanned ports
+    pub protocol: ScanProtocol,
+    /// The scanned ports, sorted
+    pub ports: Vec<u16>,
+}
+
+/// The complete outcome of a scan, as written by the output formats.
+#[derive(Debug)]
+pub struct ScanReport {
+    /// The command line the scan was started with
+    pub command: String,
+    /// The port scans run against each host, empty for a discovery-only scan
+    pub scans: Vec<ScanInfo>,
+    /// The scanned ports as given on the command line, `None` for a discovery-only scan
+    pub ports: Option<String>,
+    /// When the scan started
+    pub start_time: SystemTime,
+    /// How long the scan took
+    pub duration: Duration,
+    /// Every target, in scan order
+    pub hosts: Vec<HostReport>,
+    /// True if the scan was cancelled before every host and port was scanned
+    pub interrupted: bool,
+}
+
+impl ScanReport {
+    /// Returns the number of hosts that were found up or assumed up.
+    pub fn hosts_up(&self) -> usize {
+        self.hosts.iter().filter(|host| host.status.is_up()).count()
+    }
 }
@@ -0,0 +1,450 @@
+//! Builder style API for running complete scans.
+//!
+//! A [`Scanner`] ties the pieces of a scan together: it expands the target
+//! addresses, runs host discovery, scans the ports of every host that is up and
+//! collects everything into a [`ScanReport`]. This is what the `pscan` command
+//! line tool runs, and the easiest way to embed a scan in another program:
+//!
+//! ```no_run
+//! use pscan::{Scanner, ScanProtocol};
+//! use std::time::Duration;
+//!
+//! let report = Scanner::new("10.0.0.0/30".parse().unwrap())
+//!     .ports("22,80,443".parse().unwrap())
+//!     .protocol(ScanProtocol::Tcp)
+//!     .timeout(Duration::from_millis(200))
+//!     .run()
+//!     .unwrap();
+//! for host in report.hosts.iter().filter(|host| host.status.is_up()) {
+//!     println!("{}: {} ports scanned", host.target, host.results.len());
+//! }
+//! ```
+//!
+//! Progress can be followed while the scan runs by passing a [`ScanObserver`]
+//! to [`Scanner::run_with`], and a running scan can be stopped early through
+//! the [`CancelToken`] given to [`Scanner::cancel_token`]. A cancelled scan
+//! still returns a report, holding the hosts and ports scanned so far.
+use crate::results::{HostReport, HostStatus, ScanInfo, ScanReport, ScanResult, ScanResults};
+use crate::scanners::version::{self, ServiceDatabase};
+use crate::scanners::{
+    banner, discovery, raw, tls, AckScanner, Address, CancelToken, ExcludeList, PingProbe, PortSet,
+    ProgressTracker, ResultSink, Scan, ScanOptions, ScanProgress, ScanProtocol, SynScanner, Target,
+    TargetSet, TcpScanType, TcpScanner, UdpScanner, WindowScanner,
+};
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Receives the progress of a running scan.
+///
+/// Every method has an empty default implementation, so observers only
+/// implement the events they care about. Returning an error from any method
+/// but [`port_result`](ScanObserver::port_result) stops the scan, and the
+/// error is returned from [`Scanner::run_with`].
+pub trait ScanObserver: Send + Sync {
+    /// Called before the scan starts for every target specification that
+    /// cannot be expanded, such as an unresolvable hostname. Errors are ignored.
+    fn target_skipped(&self, _error: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once before host discovery starts, `hosts_total` is the number of targets.
+    fn scan_started(&self, _report: &ScanReport, _hosts_total: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called when a host was found up, or assumed up because discovery is skipped.
+    fn host_up(&self, _host: &HostReport) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called with every port result as soon as it is known, possibly from
+    /// several scanner threads at once. Errors are ignored.
+    fn port_result(&self, _target: &Target, _result: &ScanResult) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called when a later stage, such as banner grabbing or version detection, has added to a port
+    /// result that was already passed to [`port_result`](ScanObserver::port_result).
+    /// Errors are ignored.
+    fn port_updated(&self, _target: &Target, _result: &ScanResult) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called after every port result with the progress of the port scans of
+    /// all hosts that are up, possibly from several scanner threads at once.
+    /// Errors are ignored.
+    fn progress(&self, _progress: &ScanProgress) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called when a host has been scanned, or was found down.
+    fn host_done(&self, _host: &HostReport) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once the scan is complete.
+    fn scan_finished(&self, _report: &ScanReport) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An observer that ignores every event, used by [`Scanner::run`].
+struct NoObserver;
+
+impl ScanObserver for NoObserver {}
+
+/// Configures and runs a scan of one or more targets.
+///
+/// By default every TCP port is scanned with connect scans, using the
+/// [`Normal`](crate::scanners::TimingTemplate::Normal) timing options, and
+/// host discovery is skipped.
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    addrs: Vec<Address>,
+    excluded: Arc<ExcludeList>,
+    ports: PortSet,
+    protocol: ScanProtocol,
+    tcp_scan: TcpScanType,
+    probes: Option<Vec<PingProbe>>,
+    discover_only: bool,
+    banners: bool,
+    tls: bool,
+    services: Option<Arc<ServiceDatabase>>,
+    version_intensity: u8,
+    options: ScanOptions,
+    command: String,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Scanner {
+            addrs: Vec::new(),
+            excluded: Arc::new(ExcludeList::default()),
+            ports: PortSet::default(),
+            protocol: ScanProtocol::Tcp,
+            tcp_scan: TcpScanType::Connect,
+            probes: None,
+            discover_only: false,
+            banners: false,
+            tls: false,
+            services: None,
+            version_intensity: version::DEFAULT_VERSION_INTENSITY,
+            options: ScanOptions::default(),
+            command: String::new(),
+        }
+    }
+}
+
+impl Scanner {
+    /// Creates a scanner for a single target specification.
+    pub fn new(target: Address) -> Self {
+        Scanner::default().target(target)
+    }
+
+    /// Adds a target specification.
+    pub fn target(mut self, target: Address) -> Self {
+        self.addrs.push(target);
+        self
+    }
+
+    /// Adds several target specifications.
+    pub fn targets(mut self, targets: impl IntoIterator<Item = Address>) -> Self {
+        self.addrs.extend(targets);
+        self
+    }
+
+    /// Sets the hosts that must not be scanned, even if a target includes them.
+    pub fn exclude(mut self, excluded: ExcludeList) -> Self {
+        self.excluded = Arc::new(excluded);
+        self
+    }
+
+    /// Sets the ports to scan.
+    pub fn ports(mut self, ports: PortSet) -> Self {
+        self.ports = ports;
+        self
+    }
+
+    /// Sets the protocol to scan, unless the ports use `T:`/`U:` qualifiers.
+    pub fn protocol(mut self, protocol: ScanProtocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Sets how TCP ports are probed.
+    pub fn tcp_scan(mut self, tcp_scan: TcpScanType) -> Self {
+        self.tcp_scan = tcp_scan;
+        self
+    }
+
+    /// Sets the probe timeout used until the round trip time is known.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout_ms = timeout.as_millis() as u64;
+        self
+    }
+
+    /// Sets every timing and parallelism option at once.
+    pub fn options(mut self, options: ScanOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the token that stops the scan early once cancelled.
+    ///
+    /// This replaces the token of any [`ScanOptions`] set before.
+    pub fn cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.options.cancel = cancel;
+        self
+    }
+
+    /// Runs host discovery with the given probes and only scans hosts that answer.
+    pub fn discovery(mut self, probes: Vec<PingProbe>) -> Self {
+        self.probes = Some(probes);
+        self
+    }
+
+    /// Only runs host discovery, without scanning any ports.
+    pub fn discover_only(mut self, discover_only: bool) -> Self {
+        self.discover_only = discover_only;
+        self
+    }
+
+    /// Reads the greeting of every open TCP port after its host's port scan,
+    /// see [`banner`].
+    pub fn banners(mut self, banners: bool) -> Self {
+        self.banners = banners;
+        self
+    }
+
+    /// Completes a TLS handshake with every open TCP port after its host's
+    /// port scan and records its protocol, cipher and certificate, see [`tls`].
+    pub fn tls(mut self, tls: bool) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Identifies the service and version on every open port after its host's
+    /// port scan, using the built-in probe database, see [`version`].
+    pub fn version_detection(mut self, enabled: bool) -> Self {
+        self.services = enabled.then(ServiceDatabase::builtin);
+        self
+    }
+
+    /// Identifies the service and version on every open port using the given
+    /// probe database, such as one read from an `nmap-service-probes` file.
+    pub fn service_database(mut self, database: Arc<ServiceDatabase>) -> Self {
+        self.services = Some(database);
+        self
+    }
+
+    /// Sets the rarity, from 0 to 9, up to which version detection sends
+    /// probes to a port they are not registered for.
+    pub fn version_intensity(mut self, intensity: u8) -> Self {
+        self.version_intensity = intensity;
+        self
+    }
+
+    /// Sets the command line recorded in the report, empty by default.
+    pub fn command(mut self, command: impl Into<String>) -> Self {
+        self.command = command.into();
+        self
+    }
+
+    /// Expands the target specifications into the hosts to scan, leaving out
+    /// excluded hosts.
+    ///
+    /// Targets that cannot be expanded, such as unresolvable hostnames, are
+    /// skipped and returned as errors alongside the hosts.
+    pub fn expand_targets(&self) -> (TargetSet, Vec<String>) {
+        let mut targets = TargetSet::new();
+        let mut errors = Vec::new();
+        for addr in &self.addrs {
+            match addr.targets() {
+                Ok(expanded) => targets.extend(expanded),
+                Err(e) => errors.push(e),
+            }
+        }
+        targets.retain(|target| !self.excluded.contains(target));
+
+        (targets, errors)
+    }
+
+    /// Returns the protocols whose ports are scanned.
+    fn protocols(&self) -> Vec<ScanProtocol> {
+        match self.ports.is_qualified() {
+            true => self.ports.protocols(),
+            false => vec![self.protocol.clone()],
+        }
+    }
+
+    /// Returns the scanner implementing a protocol and TCP scan type.
+    fn scanner_for(&self, protocol: &ScanProtocol) -> Box<dyn Scan> {
+        match (protocol, &self.tcp_scan) {
+            (ScanProtocol::Tcp, TcpScanType::Connect) => Box::new(TcpScanner),
+            (ScanProtocol::Tcp, TcpScanType::Syn) => Box::new(SynScanner),
+            (ScanProtocol::Tcp, TcpScanType::Ack) => Box::new(AckScanner),
+            (ScanProtocol::Tcp, TcpScanType::Window) => Box::new(WindowScanner),
+            (ScanProtocol::Udp, _) => Box::new(UdpScanner),
+        }
+    }
+
+    /// Runs the scan and returns its report.
+    pub fn run(&self) -> Result<ScanReport, String> {
+        self.run_with(Arc::new(NoObserver))
+    }
+
+    /// Runs the scan, telling `observer` about every host and port as the scan
+    /// progresses, and returns its report.
+    ///
+    /// If the scan is cancelled, hosts that have not been reached yet are left
+    /// out of the report and [`ScanReport::interrupted`] is set.
+    pub fn run_with(&self, observer: Arc<dyn ScanObserver>) -> Result<ScanReport, String> {
+        let (targets, errors) = self.expand_targets();
+        for error in &errors {
+            let _ = observer.target_skipped(error);
+        }
+        if targets.is_empty() {
+            return Err("No valid targets to scan".to_string());
+        }
+        if self.tcp_scan.is_raw() && !self.discover_only && !raw::has_raw_privileges() {
+            return Err(format!(
+                "{} scan requires root or CAP_NET_RAW",
+                self.tcp_scan
+            ));
+        }
+        let observe = |result: io::Result<()>| result.map_err(|e| e.to_string());
+
+        let start_time = Instant::now();
+        let protocols = match self.discover_only {
+            true => Vec::new(),
+            false => self.protocols(),
+        };
+        let mut report = ScanReport {
+            command: self.command.clone(),
+            scans: protocols
+                .iter()
+                .map(|protocol| ScanInfo {
+                    scan_type: match protocol {
+                        ScanProtocol::Tcp => self.tcp_scan.to_string(),
+                        ScanProtocol::Udp => "udp".to_string(),
+                    },
+                    protocol: protocol.clone(),
+                    ports: self.ports.ports(protocol).to_vec(),
+                })
+                .collect(),
+            ports: (!self.discover_only).then(|| self.ports.to_string()),
+            start_time: SystemTime::now(),
+            duration: Duration::ZERO,
+            hosts: Vec::with_capacity(targets.len()),
+            interrupted: false,
+        };
+        observe(observer.scan_started(&report, targets.len()))?;
+
+        let replies = match self.probes {
+            Some(ref probes) => {
+                let addrs: Vec<_> = targets.iter().map(|target| target.ip).collect();
+                discovery::sweep(&addrs, probes, &self.options)
+            }
+            None => vec![None; targets.len()],
+        };
+
+        let hosts_up = match self.probes {
+            Some(_) => replies.iter().flatten().count(),
+            None => targets.len(),
+        };
+        let ports_total: usize = protocols
+            .iter()
+            .map(|protocol| self.ports.ports(protocol).len())
+            .sum();
+        let progress_observer = Arc::clone(&observer);
+        let progress = ProgressTracker::new(hosts_up * ports_total, move |progress| {
+            let _ = progress_observer.progress(progress);
+        });
+
+        for (target, reply) in targets.into_iter().zip(replies) {
+            // Discovery answers are already cut short to the hosts probed before
+            // cancelling, only port scans have to stop here.
+            if self.options.is_cancelled() && !protocols.is_empty() {
+                break;
+            }
+            let status = match (&self.probes, reply) {
+                (None, _) => HostStatus::Assumed,
+                (Some(_), Some(reply)) => HostStatus::Up(reply),
+                (Some(_), None) => HostStatus::Down,
+            };
+            let mut host = HostReport {
+                target,
+                status,
+                results: ScanResults::new(),
+            };
+
+            if host.status.is_up() {
+                observe(observer.host_up(&host))?;
+                host.results = self.scan_host(&host, &protocols, &observer, &progress);
+            }
+            observe(observer.host_done(&host))?;
+            report.hosts.push(host);
+        }
+        report.duration = start_time.elapsed();
+        report.interrupted = self.options.is_cancelled();
+        observe(observer.scan_finished(&report))?;
+
+        Ok(report)
+    }
+
+    /// Scans the ports of a host that is up with every protocol, then grabs
+    /// the banners and inspects the TLS services of its open TCP ports and
+    /// identifies the services on its open ports if enabled.
+    fn scan_host(
+        &self,
+        host: &HostReport,
+        protocols: &[ScanProtocol],
+        observer: &Arc<dyn ScanObserver>,
+        progress: &ProgressTracker,
+    ) -> ScanResults {
+        let target = host.target.clone();
+        let sink_observer = Arc::clone(observer);
+        let options = ScanOptions {
+            initial_rtt: host.status.reply().map(|reply| reply.rtt),
+            result_sink: Some(ResultSink::new(move |result| {
+                let _ = sink_observer.port_result(&target, result);
+            })),
+            progress: Some(progress.clone()),
+            ..self.options.clone()
+        };
+
+        let mut results: ScanResults = protocols
+            .iter()
+            .flat_map(|protocol| {
+                self.scanner_for(protocol)
+                    .scan(&host.target.ip, &self.ports, &options)
+            })
+            .collect();
+
+        if self.banners {
+            banner::grab_banners(&host.target.ip, &mut results, &options);
+        }
+        if self.tls {
+            let hostname = host.target.hostname.as_deref();
+            tls::inspect_ports(&host.target.ip, hostname, &mut results, &options);
+        }
+        if let Some(ref services) = self.services {
+            version::detect_services(
+                &host.target.ip,
+                &mut results,
+                &options,
+                services,
+                self.version_intensity,
+            );
+        }
+        for result in results
+            .iter()
+            .filter(|r| r.banner.is_some() || r.service.is_some() || r.tls.is_some())
+        {
+            let _ = observer.port_updated(&host.target, result);
+        }
+
+        results
+    }
+}
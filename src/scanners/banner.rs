@@ -0,0 +1,133 @@
+//! Banner grabbing for open TCP ports.
+//!
+//! Many services greet a client before it sends anything: SSH announces its
+//! version, SMTP, FTP, POP3 and IMAP servers send a ready message and MySQL
+//! sends a handshake packet. After the port scan of a host, [`grab_banners`]
+//! connects to each of its open TCP ports again, waits briefly for such a
+//! greeting and stores it on the port's [`ScanResult`]. Ports whose service
+//! waits for the client to speak first, such as HTTP, get no banner.
+//!
+//! Banners are sanitized before they are stored: line breaks, control bytes
+//! and non-ASCII bytes are escaped, and long banners are cut short.
+//!
+//! # Example
+//! ```no_run
+//! use pscan::scanners::banner;
+//! use std::time::Duration;
+//!
+//! let addr = "127.0.0.1:22".parse().unwrap();
+//! if let Ok(Some(banner)) = banner::grab(&addr, Duration::from_secs(2)) {
+//!     println!("22/tcp: {}", banner);
+//! }
+//! ```
+use crate::results::{PortState, ScanResult};
+use crate::scanners::{ScanOptions, ScanProtocol};
+use std::fmt::Write as _;
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait for the connect and then for the greeting.
+pub const BANNER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Maximum number of bytes kept from a greeting.
+pub const MAX_BANNER_LEN: usize = 256;
+
+/// Maximum number of ports whose banners are grabbed at once.
+pub const MAX_BANNER_WORKERS: usize = 32;
+
+/// Connects to a TCP port and returns the sanitized greeting the service sends.
+///
+/// Returns `None` if the service sent nothing within `timeout`, or closed the
+/// connection without a word.
+pub fn grab(addr: &SocketAddr, timeout: Duration) -> io::Result<Option<String>> {
+    let mut stream = TcpStream::connect_timeout(addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+
+    let mut buffer = [0u8; MAX_BANNER_LEN];
+    let n = match stream.read(&mut buffer) {
+        Ok(n) => n,
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            0
+        }
+        Err(e) => return Err(e),
+    };
+
+    let banner = sanitize(&buffer[..n]);
+    Ok((!banner.is_empty()).then_some(banner))
+}
+
+/// Turns raw greeting bytes into a single printable line.
+///
+/// Trailing whitespace and line breaks are dropped, inner line breaks are
+/// written as `\r` and `\n`, and other control or non-ASCII bytes as `\xNN`.
+pub fn sanitize(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(0, |i| i + 1);
+
+    let mut banner = String::with_capacity(end);
+    for &byte in &bytes[..end] {
+        match byte {
+            b'\r' => banner.push_str("\\r"),
+            b'\n' => banner.push_str("\\n"),
+            b'\\' => banner.push_str("\\\\"),
+            b' '..=b'~' => banner.push(byte as char),
+            _ => {
+                let _ = write!(banner, "\\x{:02x}", byte);
+            }
+        }
+    }
+
+    banner
+}
+
+/// Grabs the banners of every open TCP port in `results` and stores them on
+/// the results.
+///
+/// Up to `max_parallelism` (at most [`MAX_BANNER_WORKERS`]) ports are
+/// connected to at once. No new ports are connected to once the scan is
+/// cancelled.
+pub fn grab_banners(addr: &IpAddr, results: &mut [ScanResult], options: &ScanOptions) {
+    let ports: Vec<_> = results
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| r.protocol == ScanProtocol::Tcp && r.state == PortState::Open)
+        .map(|(i, r)| (i, r.port))
+        .collect();
+    let next = AtomicUsize::new(0);
+    let banners = Mutex::new(Vec::new());
+    let n_workers = options
+        .max_parallelism
+        .min(MAX_BANNER_WORKERS)
+        .min(ports.len());
+
+    thread::scope(|scope| {
+        for _ in 0..n_workers {
+            scope.spawn(|| loop {
+                if options.is_cancelled() {
+                    return;
+                }
+                let Some(&(i, port)) = ports.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                    return;
+                };
+                if let Ok(Some(banner)) = grab(&SocketAddr::new(*addr, port), BANNER_TIMEOUT) {
+                    banners.lock().unwrap().push((i, banner));
+                }
+            });
+        }
+    });
+
+    for (i, banner) in banners.into_inner().unwrap() {
+        results[i].banner = Some(banner);
+    }
+}
@@ -0,0 +1,368 @@
+//! Host discovery probes.
+//!
+//! Before a target is port scanned it is checked for signs of life. Several kinds
+//! of probes are supported, and a host counts as up as soon as any one of them is
+//! answered:
+//!
+//! - ICMP echo request, answered by an echo reply
+//! - ICMP timestamp request, answered by a timestamp reply (IPv4 only)
+//! - TCP SYN to a port, answered by a SYN/ACK from an open port or a RST from a closed one
+//! - TCP ACK to a port, answered by a RST whether the port is open or closed
+//! - TCP connect to a port, which succeeds or is refused
+//! - UDP datagram to a port, answered by a reply or an ICMP port unreachable
+//!
+//! Hosts on a directly attached network are resolved with ARP or IPv6 Neighbor
+//! Discovery instead, which also reveals their MAC address.
+//!
+//! The timestamp, SYN and ACK probes are crafted on raw sockets and require root
+//! privileges or the `CAP_NET_RAW` capability, see [`PingProbe::unprivileged`].
+//!
+//! [`sweep`] runs discovery on many hosts concurrently, with up to
+//! `max_parallelism` hosts waiting for answers at once.
+//!
+//! # Example
+//! ```no_run
+//! use pscan::scanners::discovery::{discover, PingProbe};
+//!
+//! let addr = "192.168.1.1".parse().unwrap();
+//! if let Some(reply) = discover(&addr, &[PingProbe::Echo, PingProbe::Syn(443)]) {
+//!     println!("Host is up, received {}", reply.reason);
+//! }
+//! ```
+use crate::scanners::neighbor::MacAddr;
+#[cfg(target_os = "linux")]
+use crate::scanners::neighbor::{self, Interface};
+use crate::scanners::timing::Timing;
+use crate::scanners::{payloads, raw, ScanOptions};
+use crate::utils;
+use socket2::Protocol;
+use std::fmt::Display;
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Maximum number of hosts probed at once by [`sweep`].
+pub const MAX_SWEEP_WORKERS: usize = 256;
+
+/// How long to wait for a discovery probe to be answered.
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// ICMPv4 timestamp request and reply message types.
+const ICMP_TIMESTAMP: u8 = 13;
+const ICMP_TIMESTAMP_REPLY: u8 = 14;
+
+/// A single host discovery probe.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PingProbe {
+    /// ICMP echo request
+    Echo,
+    /// ICMP timestamp request
+    Timestamp,
+    /// Raw TCP SYN to a port
+    Syn(u16),
+    /// Raw TCP ACK to a port
+    Ack(u16),
+    /// Full TCP connect to a port
+    Connect(u16),
+    /// UDP datagram to a port
+    Udp(u16),
+}
+
+/// Describes the first answer to a host's discovery probes.
+#[derive(Debug, Clone)]
+pub struct PingReply {
+    /// Time between sending the probe and receiving the answer
+    pub rtt: Duration,
+    /// What was received, e.g. `echo-reply` or `syn-ack on 443/tcp`
+    pub reason: String,
+    /// The host's hardware address, if it was resolved on the local network
+    pub mac: Option<MacAddr>,
+}
+
+impl PingReply {
+    pub fn new(rtt: Duration, reason: String) -> Self {
+        PingReply {
+            rtt,
+            reason,
+            mac: None,
+        }
+    }
+}
+
+impl PingProbe {
+    /// Returns the probe to send in place of this one without raw socket privileges.
+    ///
+    /// TCP SYN and ACK pings fall back to a connect to the same port, timestamp
+    /// pings have no fallback.
+    pub fn unprivileged(&self) -> Option<PingProbe> {
+        match self {
+            PingProbe::Syn(port) | PingProbe::Ack(port) => Some(PingProbe::Connect(*port)),
+            PingProbe::Timestamp => None,
+            probe => Some(probe.clone()),
+        }
+    }
+
+    /// Sends the probe and waits for an answer.
+    fn send(&self, addr: &IpAddr) -> io::Result<Option<PingReply>> {
+        match self {
+            PingProbe::Echo => Ok(echo_ping(addr)),
+            PingProbe::Timestamp => timestamp_ping(addr),
+            PingProbe::Syn(port) => tcp_ping(addr, *port, raw::TCP_SYN),
+            PingProbe::Ack(port) => tcp_ping(addr, *port, raw::TCP_ACK),
+            PingProbe::Connect(port) => Ok(connect_ping(addr, *port)),
+            PingProbe::Udp(port) => udp_ping(addr, *port),
+        }
+    }
+}
+
+impl Display for PingProbe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PingProbe::Echo => write!(f, "ICMP echo ping"),
+            PingProbe::Timestamp => write!(f, "ICMP timestamp ping"),
+            PingProbe::Syn(port) => write!(f, "TCP SYN ping to port {}", port),
+            PingProbe::Ack(port) => write!(f, "TCP ACK ping to port {}", port),
+            PingProbe::Connect(port) => write!(f, "TCP connect ping to port {}", port),
+            PingProbe::Udp(port) => write!(f, "UDP ping to port {}", port),
+        }
+    }
+}
+
+/// Sends all probes to a host at once and returns the first answer, or `None`
+/// if no probe was answered in time.
+///
+/// Hosts on a directly attached network are resolved with ARP or Neighbor
+/// Discovery instead when raw socket privileges are available on Linux, see
+/// the [`neighbor` module](crate::scanners::neighbor).
+pub fn discover(addr: &IpAddr, probes: &[PingProbe]) -> Option<PingReply> {
+    #[cfg(target_os = "linux")]
+    if let Some(interface) = neighbor::on_link_interface(addr) {
+        if raw::has_raw_privileges() {
+            match neighbor_ping(addr, &interface) {
+                Ok(reply) => return reply,
+                Err(e) => eprintln!("Neighbor discovery for {} failed: {}", addr, e),
+            }
+        }
+    }
+
+    let (tx, rx) = mpsc::channel();
+    for probe in probes {
+        let (tx, addr, probe) = (tx.clone(), *addr, probe.clone());
+        thread::spawn(move || match probe.send(&addr) {
+            Ok(Some(reply)) => {
+                let _ = tx.send(reply);
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("{} to {} failed: {}", probe, addr, e),
+        });
+    }
+    drop(tx);
+
+    // Fails once every probe has given up without an answer.
+    rx.recv().ok()
+}
+
+/// Runs [`discover`] on every address and returns the answers in the same order.
+///
+/// Hosts are probed concurrently by up to `max_parallelism` (at most
+/// [`MAX_SWEEP_WORKERS`]) workers, with the start of each host's discovery paced
+/// by the scan's [`Timing`]. Once the scan is cancelled no more hosts are
+/// probed and only the answers of the hosts probed so far are returned.
+pub fn sweep(
+    addrs: &[IpAddr],
+    probes: &[PingProbe],
+    options: &ScanOptions,
+) -> Vec<Option<PingReply>> {
+    let timing = Timing::new(options);
+    let next = AtomicUsize::new(0);
+    let replies = Mutex::new(vec![None; addrs.len()]);
+    let n_workers = options
+        .max_parallelism
+        .min(MAX_SWEEP_WORKERS)
+        .min(addrs.len());
+
+    thread::scope(|scope| {
+        for _ in 0..n_workers {
+            scope.spawn(|| loop {
+                if options.is_cancelled() {
+                    return;
+                }
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(addr) = addrs.get(i) else {
+                    return;
+                };
+                timing.wait_send();
+                let reply = discover(addr, probes);
+                replies.lock().unwrap()[i] = reply;
+            });
+        }
+    });
+
+    let mut replies = replies.into_inner().unwrap();
+    replies.truncate(next.into_inner());
+
+    replies
+}
+
+/// Resolves an on-link host's MAC address with ARP or Neighbor Discovery.
+#[cfg(target_os = "linux")]
+fn neighbor_ping(addr: &IpAddr, interface: &Interface) -> io::Result<Option<PingReply>> {
+    let sent = Instant::now();
+    let mac = neighbor::resolve(addr, interface, DISCOVERY_TIMEOUT)?;
+    let reason = match addr {
+        IpAddr::V4(_) => "arp-response",
+        IpAddr::V6(_) => "nd-response",
+    };
+
+    Ok(mac.map(|mac| PingReply {
+        rtt: sent.elapsed(),
+        reason: reason.to_string(),
+        mac: Some(mac),
+    }))
+}
+
+/// Sends an ICMP echo request.
+fn echo_ping(addr: &IpAddr) -> Option<PingReply> {
+    utils::ping_host(addr).ok().map(|reply| {
+        PingReply::new(
+            Duration::from_millis(u64::from(reply.rtt)),
+            "echo-reply".to_string(),
+        )
+    })
+}
+
+/// Sends an ICMP timestamp request, which some hosts answer even though they drop echo requests.
+fn timestamp_ping(addr: &IpAddr) -> io::Result<Option<PingReply>> {
+    if addr.is_ipv6() {
+        // ICMPv6 has no timestamp message.
+        return Ok(None);
+    }
+    let mut socket = raw::open_raw_socket(addr, Protocol::ICMPV4)?;
+    let id = raw::random_u32() as u16;
+    let midnight_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        % (24 * 60 * 60 * 1000);
+
+    let mut request = [0u8; 20];
+    request[0] = ICMP_TIMESTAMP;
+    request[4..6].copy_from_slice(&id.to_be_bytes());
+    request[6..8].copy_from_slice(&1u16.to_be_bytes());
+    request[8..12].copy_from_slice(&(midnight_ms as u32).to_be_bytes());
+    let checksum = raw::internet_checksum(&[&request]);
+    request[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+    let sent = Instant::now();
+    socket.send(&request)?;
+    let answered = wait_for_reply(&mut socket, sent, |packet| {
+        let icmp = raw::strip_ip_header(addr, packet)?;
+        let reply_id = u16::from_be_bytes([*icmp.get(4)?, *icmp.get(5)?]);
+        (icmp[0] == ICMP_TIMESTAMP_REPLY && reply_id == id).then(|| "timestamp-reply".to_string())
+    })?;
+
+    Ok(answered.map(|reason| PingReply::new(sent.elapsed(), reason)))
+}
+
+/// Sends a bare TCP segment with the given flags to a port and waits for any
+/// TCP answer from it.
+fn tcp_ping(addr: &IpAddr, port: u16, flags: u8) -> io::Result<Option<PingReply>> {
+    let mut socket = raw::open_raw_socket(addr, Protocol::TCP)?;
+    let src = SocketAddr::new(
+        raw::local_addr_for(addr)?,
+        40000 + (raw::random_u32() % 20000) as u16,
+    );
+    let dst = SocketAddr::new(*addr, port);
+    let segment = raw::build_tcp_segment(&src, &dst, raw::random_u32(), flags);
+
+    let sent = Instant::now();
+    socket.send(&segment)?;
+    let answered = wait_for_reply(&mut socket, sent, |packet| {
+        let reply = raw::parse_tcp_reply(addr, packet)?;
+        if reply.src_port != port || reply.dst_port != src.port() {
+            return None;
+        }
+        let kind = match reply.flags & (raw::TCP_SYN | raw::TCP_RST) {
+            raw::TCP_SYN => "syn-ack",
+            _ => "reset",
+        };
+        Some(format!("{} on {}/tcp", kind, port))
+    })?;
+
+    Ok(answered.map(|reason| PingReply::new(sent.elapsed(), reason)))
+}
+
+/// Reads packets from a raw socket until `matches` recognizes one as the
+/// answer to the probe sent at `sent`, or the discovery timeout expires.
+fn wait_for_reply<F>(
+    socket: &mut socket2::Socket,
+    sent: Instant,
+    mut matches: F,
+) -> io::Result<Option<String>>
+where
+    F: FnMut(&[u8]) -> Option<String>,
+{
+    let mut buffer = [0u8; 1500];
+    let mut answer = None;
+    while answer.is_none() {
+        let remaining = (sent + DISCOVERY_TIMEOUT).saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        raw::read_packets(socket, &mut buffer, remaining, |packet| {
+            if answer.is_none() {
+                answer = matches(packet);
+            }
+        })?;
+    }
+
+    Ok(answer)
+}
+
+/// Connects to a port, a refused connection proves the host is up just as well.
+fn connect_ping(addr: &IpAddr, port: u16) -> Option<PingReply> {
+    let sent = Instant::now();
+    let reason = match TcpStream::connect_timeout(&SocketAddr::new(*addr, port), DISCOVERY_TIMEOUT)
+    {
+        Ok(_) => "connect",
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => "conn-refused",
+        Err(_) => return None,
+    };
+
+    Some(PingReply::new(
+        sent.elapsed(),
+        format!("{} on {}/tcp", reason, port),
+    ))
+}
+
+/// Sends a UDP datagram to a port, expecting either a reply from an open port
+/// or an ICMP port unreachable from a closed one.
+fn udp_ping(addr: &IpAddr, port: u16) -> io::Result<Option<PingReply>> {
+    let socket = match addr {
+        IpAddr::V4(_) => UdpSocket::bind("0.0.0.0:0")?,
+        IpAddr::V6(_) => UdpSocket::bind("[::]:0")?,
+    };
+    // A connected socket has ICMP errors reported on it by the operating system.
+    socket.connect(SocketAddr::new(*addr, port))?;
+    socket.set_read_timeout(Some(DISCOVERY_TIMEOUT))?;
+    let data = payloads::payload_for(port)
+        .map(|p| p.data)
+        .unwrap_or_default();
+
+    let sent = Instant::now();
+    socket.send(data)?;
+    let reason = match socket.recv(&mut [0u8; 512]) {
+        Ok(_) => "udp-response",
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => "port-unreach",
+        // Timed out, or the host was reported unreachable.
+        Err(_) => return Ok(None),
+    };
+
+    Ok(Some(PingReply::new(
+        sent.elapsed(),
+        format!("{} on {}/udp", reason, port),
+    )))
+}
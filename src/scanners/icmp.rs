@@ -0,0 +1,162 @@
+//! ICMP driven classification of UDP ports.
+//!
+//! A closed UDP port answers with an ICMP port unreachable message, while a
+//! firewall either drops the probe or answers with a different unreachable code.
+//! The [`IcmpListener`] reads those messages from a raw ICMP socket in a
+//! background thread and records a verdict for each probed port:
+//!
+//! - Port unreachable (ICMPv4 type 3 code 3, ICMPv6 type 1 code 4) means `closed`
+//! - Any other destination unreachable code means `filtered`
+//!
+//! Each verdict carries the kind of unreachable message as its reason, e.g.
+//! `port-unreach` or `admin-prohibited`.
+//!
+//! This is independent of how the operating system reports ICMP errors on UDP
+//! sockets, but requires root privileges or the `CAP_NET_RAW` capability.
+use crate::results::PortState;
+use crate::scanners::raw;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// IP protocol number of UDP.
+const IPPROTO_UDP: u8 = 17;
+
+/// How often the listener thread checks whether it should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The port state implied by an ICMP message about a probed port.
+#[derive(Debug, Clone)]
+pub struct Verdict {
+    /// The state of the port
+    pub state: PortState,
+    /// The kind of unreachable message, e.g. `port-unreach`
+    pub reason: &'static str,
+    /// When the message arrived
+    pub received: Instant,
+}
+
+/// Listens for ICMP unreachable messages about UDP probes sent to one target.
+pub struct IcmpListener {
+    verdicts: Arc<Mutex<HashMap<u16, Verdict>>>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl IcmpListener {
+    /// Opens a raw ICMP socket and starts listening for messages about `target`.
+    pub fn start(target: IpAddr) -> io::Result<Self> {
+        let mut socket = match target {
+            IpAddr::V4(_) => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))?,
+            IpAddr::V6(_) => Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::ICMPV6))?,
+        };
+        let verdicts = Arc::new(Mutex::new(HashMap::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let verdicts = Arc::clone(&verdicts);
+            let stop = Arc::clone(&stop);
+            thread::Builder::new()
+                .name("icmp-listener".to_string())
+                .spawn(move || {
+                    let mut buffer = [0u8; 1500];
+                    while !stop.load(Ordering::Relaxed) {
+                        let read =
+                            raw::read_packets(&mut socket, &mut buffer, POLL_INTERVAL, |packet| {
+                                if let Some((port, (state, reason))) =
+                                    parse_unreachable(&target, packet)
+                                {
+                                    let verdict = Verdict {
+                                        state,
+                                        reason,
+                                        received: Instant::now(),
+                                    };
+                                    verdicts.lock().unwrap().insert(port, verdict);
+                                }
+                            });
+                        if let Err(e) = read {
+                            eprintln!("ICMP listener failed: {}", e);
+                            return;
+                        }
+                    }
+                })?
+        };
+
+        Ok(IcmpListener {
+            verdicts,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// Returns the verdict of an ICMP message about a probe to `port`, if one arrived.
+    pub fn verdict(&self, port: u16) -> Option<Verdict> {
+        self.verdicts.lock().unwrap().get(&port).cloned()
+    }
+}
+
+impl Drop for IcmpListener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Parses an ICMP destination unreachable message quoting a UDP datagram sent to
+/// `target`, returning the quoted destination port and the state and reason it implies.
+fn parse_unreachable(target: &IpAddr, packet: &[u8]) -> Option<(u16, (PortState, &'static str))> {
+    let icmp = raw::strip_ip_header(target, packet)?;
+    let (icmp_type, code) = (*icmp.first()?, *icmp.get(1)?);
+    let quoted = icmp.get(8..)?;
+
+    let (state, udp) = match target {
+        IpAddr::V4(target) => {
+            if icmp_type != 3 || quoted.get(9) != Some(&IPPROTO_UDP) {
+                return None;
+            }
+            let dst: [u8; 4] = quoted.get(16..20)?.try_into().ok()?;
+            if Ipv4Addr::from(dst) != *target {
+                return None;
+            }
+            let state = match code {
+                0 => (PortState::Filtered, "net-unreach"),
+                1 => (PortState::Filtered, "host-unreach"),
+                2 => (PortState::Filtered, "proto-unreach"),
+                3 => (PortState::Closed, "port-unreach"),
+                9 => (PortState::Filtered, "net-prohibited"),
+                10 => (PortState::Filtered, "host-prohibited"),
+                13 => (PortState::Filtered, "admin-prohibited"),
+                _ => (PortState::Filtered, "unreach"),
+            };
+            let ihl = usize::from(quoted[0] & 0x0f) * 4;
+            (state, quoted.get(ihl..)?)
+        }
+        IpAddr::V6(target) => {
+            if icmp_type != 1 || quoted.get(6) != Some(&IPPROTO_UDP) {
+                return None;
+            }
+            let dst: [u8; 16] = quoted.get(24..40)?.try_into().ok()?;
+            if Ipv6Addr::from(dst) != *target {
+                return None;
+            }
+            let state = match code {
+                0 => (PortState::Filtered, "net-unreach"),
+                1 => (PortState::Filtered, "admin-prohibited"),
+                3 => (PortState::Filtered, "host-unreach"),
+                4 => (PortState::Closed, "port-unreach"),
+                _ => (PortState::Filtered, "unreach"),
+            };
+            (state, quoted.get(40..)?)
+        }
+    };
+
+    let port = u16::from_be_bytes([*udp.get(2)?, *udp.get(3)?]);
+    Some((port, state))
+}
@@ -3,133 +3,203 @@
 //! This module provides the core components for network port scanning:
 //!
 //! # Key Components
-//! - [`Address`] - Represents network addresses (IP or hostname)
+//! - [`Address`] - Represents target specifications (IP, hostname, CIDR block or range)
+//! - [`Target`] - A single host expanded from an [`Address`]
 //! - [`PortRange`] - Defines ranges of ports to scan
+//! - [`PortSet`] - Per-protocol port lists built from ports, ranges and service names
 //! - [`ScanProtocol`] - Specifies supported protocols (TCP/UDP)
+//! - [`ScanOptions`] - Tuning parameters shared by all scanners
+//! - [`ResultSink`] - Receives each port result as soon as it is known
+//! - [`CancelToken`] and [`ProgressTracker`] - Stop a running scan and follow its progress
+//! - [`Timing`](timing::Timing) - Adaptive probe timeouts, retransmissions and rate limits
+//! - [`TimingTemplate`] - Named presets for the timing options
 //! - [`Scan`] trait - Core scanning interface
-//! - Protocol-specific scanners ([`TcpScanner`], [`UdpScanner`])
+//! - [`PingProbe`] - Host discovery probes run before a target is scanned
+//! - [`banner`] - Greetings read from open TCP ports after the port scan
+//! - [`version`] - Service and version detection of open ports
+//! - [`tls`] - TLS handshakes and certificates of open TCP ports
+//! - [`TcpScanType`] - Selects how TCP ports are probed
+//! - Protocol-specific scanners ([`TcpScanner`], [`SynScanner`], [`AckScanner`],
+//!   [`WindowScanner`], [`UdpScanner`])
 //!
 //! # Example
 //! ```no_run
-//! use pscan::scanners::{PortRange, TcpScanner, Scan};
+//! use pscan::scanners::{PortSet, ScanOptions, TcpScanner, Scan};
 //!
-//! let scanner = TcpScanner::new();
-//! let range = PortRange::new(1, 1024);
+//! let scanner = TcpScanner;
+//! let ports: PortSet = "22,80,443,8000-8100".parse().unwrap();
 //! let addr = "127.0.0.1".parse().unwrap();
-//! let results = scanner.scan(&addr, &range);
+//! let results = scanner.scan(&addr, &ports, &ScanOptions::default());
 //! ```
-use crate::results::ScanResults;
+use crate::results::{ScanResult, ScanResults};
 use clap::ValueEnum;
-use std::fmt::{self, Display, Formatter};
-use std::net::IpAddr;
-use std::str::FromStr;
-
+use std::fmt::{Debug, Display};
+use std::sync::Arc;
+
+pub mod banner;
+pub mod discovery;
+pub mod icmp;
+pub mod neighbor;
+pub mod payloads;
+pub mod ports;
+pub mod progress;
 pub mod protocols;
+pub mod raw;
+pub mod targets;
+pub mod timing;
+pub mod tls;
+pub mod version;
+pub use discovery::PingProbe;
+pub use ports::{PortRange, PortSet};
+pub use progress::{CancelToken, ProgressTracker, ScanProgress};
+pub use protocols::AckScanner;
+pub use protocols::SynScanner;
 pub use protocols::TcpScanner;
 pub use protocols::UdpScanner;
+pub use protocols::WindowScanner;
+pub use targets::{parse_addr, read_address_list, Address, ExcludeList, Target, TargetSet};
+pub use timing::TimingTemplate;
 
-/// Represents a network address that can be either an IP address or a hostname
-#[derive(Debug, Clone)]
-pub enum Address {
-    /// An IP address (either IPv4 or IPv6)
-    Ip(IpAddr),
-    /// A domain name or hostname as a string
-    Hostname(String),
-}
-
-impl Display for Address {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        match self {
-            Address::Ip(ip) => write!(f, "{}", ip),
-            Address::Hostname(hostname) => write!(f, "{}", hostname),
-        }
-    }
+/// Specifies the protocol to be used for port scanning.
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum ScanProtocol {
+    /// TCP (Transmission Control Protocol) scanning mode
+    Tcp,
+    /// UDP (User Datagram Protocol) scanning mode
+    Udp,
 }
 
-impl FromStr for Address {
-    type Err = String;
+impl Display for ScanProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let protocol = match self {
+            ScanProtocol::Tcp => "tcp",
+            ScanProtocol::Udp => "udp",
+        };
 
-    fn from_str(input: &str) -> Result<Self, Self::Err> {
-        if let Ok(ip) = input.parse::<IpAddr>() {
-            Ok(Address::Ip(ip))
-        } else {
-            Ok(Address::Hostname(input.to_string()))
-        }
+        write!(f, "{}", protocol)
     }
 }
 
-pub fn parse_addr(input: &str) -> Result<Address, String> {
-    input.parse::<Address>()
-}
-
-/// Represents a range of ports to be scanned.
-#[derive(Debug, Clone)]
-pub struct PortRange {
-    /// The first port number in the range (inclusive)
-    pub start: u16,
-    /// The last port number in the range (inclusive)
-    pub end: u16,
+/// Specifies the technique used to probe TCP ports.
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum TcpScanType {
+    /// Full TCP connect through the operating system's TCP stack
+    Connect,
+    /// Half-open scan with crafted SYN segments, requires raw socket privileges
+    Syn,
+    /// Firewall rule mapping with bare ACK segments, requires raw socket privileges
+    Ack,
+    /// ACK scan that reads the RST window size to tell open from closed ports
+    Window,
 }
 
-impl Default for PortRange {
-    fn default() -> Self {
-        PortRange {
-            start: 1,
-            end: 65535,
-        }
+impl TcpScanType {
+    /// Returns true if the scan type sends crafted packets through a raw socket.
+    pub fn is_raw(&self) -> bool {
+        *self != TcpScanType::Connect
     }
 }
 
-impl Display for PortRange {
+impl Display for TcpScanType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}-{}", self.start, self.end)
+        let scan_type = match self {
+            TcpScanType::Connect => "connect",
+            TcpScanType::Syn => "syn",
+            TcpScanType::Ack => "ack",
+            TcpScanType::Window => "window",
+        };
+
+        write!(f, "{}", scan_type)
     }
 }
 
-impl FromStr for PortRange {
-    type Err = String;
+/// Tuning parameters passed to every scanner.
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// How long to wait for a probe to be answered before any RTT is known, in milliseconds
+    pub timeout_ms: u64,
+    /// The maximum number of probes in flight at once
+    pub max_parallelism: usize,
+    /// How many times an unanswered probe is retransmitted
+    pub max_retries: u32,
+    /// Lower bound of the adaptive probe timeout, in milliseconds
+    pub min_rtt_timeout_ms: u64,
+    /// Upper bound of the adaptive probe timeout, in milliseconds
+    pub max_rtt_timeout_ms: u64,
+    /// Round trip time measured during host discovery, used to seed the timeout
+    pub initial_rtt: Option<std::time::Duration>,
+    /// Minimum delay between two probes, in milliseconds
+    pub scan_delay_ms: u64,
+    /// Send at least this many probes per second, exceeding `max_parallelism` if needed
+    pub min_rate: Option<f64>,
+    /// Send at most this many probes per second
+    pub max_rate: Option<f64>,
+    /// Called with every port result as soon as it is known
+    pub result_sink: Option<ResultSink>,
+    /// Counts finished ports and reports the scan's progress
+    pub progress: Option<ProgressTracker>,
+    /// Stops the scan early once cancelled, keeping the results so far
+    pub cancel: CancelToken,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<&str> = s.split('-').collect();
-        if parts.len() != 2 {
-            return Err("Invalid port range".to_string());
+impl ScanOptions {
+    /// Passes a port result to the result sink and progress tracker, if any.
+    ///
+    /// Scanners call this the moment a port's state is decided, before the
+    /// results are sorted and returned from [`Scan::scan`].
+    pub fn emit(&self, result: &ScanResult) {
+        if let Some(ref sink) = self.result_sink {
+            sink.send(result);
         }
-
-        let start = parts[0].parse().map_err(|_| "Invalid start port number")?;
-        let end = parts[1].parse().map_err(|_| "Invalid end port number")?;
-        if start > end {
-            return Err("Start port must be less than or equal to end port".to_string());
+        if let Some(ref progress) = self.progress {
+            progress.record(result);
         }
+    }
 
-        Ok(PortRange { start, end })
+    /// Returns true once the scan has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
     }
 }
 
-/// Specifies the protocol to be used for port scanning.
-#[derive(Debug, Clone, ValueEnum)]
-pub enum ScanProtocol {
-    /// TCP (Transmission Control Protocol) scanning mode
-    Tcp,
-    /// UDP (User Datagram Protocol) scanning mode
-    Udp,
+/// A callback that streams port results out of a running scan.
+///
+/// The callback may be called from several scanner threads at once.
+#[derive(Clone)]
+pub struct ResultSink(Arc<dyn Fn(&ScanResult) + Send + Sync>);
+
+impl ResultSink {
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&ScanResult) + Send + Sync + 'static,
+    {
+        ResultSink(Arc::new(callback))
+    }
+
+    /// Calls the callback with a port result.
+    pub fn send(&self, result: &ScanResult) {
+        (self.0)(result)
+    }
 }
 
-impl Display for ScanProtocol {
+impl Debug for ResultSink {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let protocol = match self {
-            ScanProtocol::Tcp => "tcp",
-            ScanProtocol::Udp => "udp",
-        };
+        f.write_str("ResultSink")
+    }
+}
 
-        write!(f, "{}", protocol)
+impl Default for ScanOptions {
+    fn default() -> Self {
+        TimingTemplate::Normal.into()
     }
 }
 
 /// A trait defining the interface for port scanning implementations.
 ///
 /// This trait must be implemented by any scanner that performs port scanning operations,
-/// regardless of the protocol or method used.
+/// regardless of the protocol or method used. Implementations pass every result to
+/// [`ScanOptions::emit`] as soon as it is known, and stop sending probes once
+/// [`ScanOptions::cancel`] is cancelled, returning the results gathered so far.
 pub trait Scan {
-    fn scan(&self, addr: &std::net::IpAddr, port_range: &PortRange, timeout_ms: u64)
-        -> ScanResults;
+    fn scan(&self, addr: &std::net::IpAddr, ports: &PortSet, options: &ScanOptions) -> ScanResults;
 }
@@ -0,0 +1,278 @@
+//! ARP and Neighbor Discovery over Linux packet and raw sockets.
+use super::MacAddr;
+use crate::scanners::raw;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::time::{Duration, Instant};
+
+/// EtherType of ARP.
+const ETH_P_ARP: u16 = 0x0806;
+
+/// ARP operation codes.
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+
+/// ICMPv6 Neighbor Solicitation and Advertisement message types.
+const ICMPV6_NEIGHBOR_SOLICITATION: u8 = 135;
+const ICMPV6_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+/// Neighbor Discovery option types carrying a link-layer address.
+const ND_OPT_SOURCE_LINK_ADDR: u8 = 1;
+const ND_OPT_TARGET_LINK_ADDR: u8 = 2;
+
+/// A local network interface address that targets can be resolved on.
+#[derive(Debug, Clone)]
+pub struct Interface {
+    /// Interface index
+    pub index: u32,
+    /// Hardware address of the interface
+    pub mac: MacAddr,
+    /// One of the interface's IP addresses
+    pub addr: IpAddr,
+    /// The netmask of `addr`
+    pub netmask: IpAddr,
+}
+
+impl Interface {
+    /// Returns true if `addr` is in the subnet of this interface address.
+    fn contains(&self, addr: &IpAddr) -> bool {
+        match (self.addr, self.netmask, addr) {
+            (IpAddr::V4(local), IpAddr::V4(mask), IpAddr::V4(addr)) => {
+                local.to_bits() & mask.to_bits() == addr.to_bits() & mask.to_bits()
+            }
+            (IpAddr::V6(local), IpAddr::V6(mask), IpAddr::V6(addr)) => {
+                local.to_bits() & mask.to_bits() == addr.to_bits() & mask.to_bits()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Lists the IP addresses of every interface that is up, has an Ethernet
+/// hardware address and is not a loopback interface.
+fn local_interfaces() -> io::Result<Vec<Interface>> {
+    let mut ifaddrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut links = HashMap::new();
+    let mut addrs = Vec::new();
+    let mut cursor = ifaddrs;
+    while let Some(ifaddr) = unsafe { cursor.as_ref() } {
+        cursor = ifaddr.ifa_next;
+        let flags = ifaddr.ifa_flags as libc::c_int;
+        if ifaddr.ifa_addr.is_null() || flags & libc::IFF_UP == 0 || flags & libc::IFF_LOOPBACK != 0
+        {
+            continue;
+        }
+        let name = unsafe { CStr::from_ptr(ifaddr.ifa_name) }.to_owned();
+
+        match i32::from(unsafe { (*ifaddr.ifa_addr).sa_family }) {
+            libc::AF_PACKET => {
+                let link = unsafe { &*(ifaddr.ifa_addr as *const libc::sockaddr_ll) };
+                if link.sll_halen == 6 {
+                    let mut mac = [0u8; 6];
+                    mac.copy_from_slice(&link.sll_addr[..6]);
+                    links.insert(name, (link.sll_ifindex as u32, MacAddr(mac)));
+                }
+            }
+            libc::AF_INET | libc::AF_INET6 if !ifaddr.ifa_netmask.is_null() => {
+                let addr = unsafe { sockaddr_to_ip(ifaddr.ifa_addr) };
+                let netmask = unsafe { sockaddr_to_ip(ifaddr.ifa_netmask) };
+                addrs.push((name, addr, netmask));
+            }
+            _ => {}
+        }
+    }
+    unsafe { libc::freeifaddrs(ifaddrs) };
+
+    Ok(addrs
+        .into_iter()
+        .filter_map(|(name, addr, netmask)| {
+            let (index, mac) = *links.get(&name)?;
+            Some(Interface {
+                index,
+                mac,
+                addr,
+                netmask,
+            })
+        })
+        .collect())
+}
+
+/// Reads the IP address out of an `AF_INET` or `AF_INET6` socket address.
+///
+/// # Safety
+///
+/// `addr` must point to a `sockaddr_in` or `sockaddr_in6` matching its family.
+unsafe fn sockaddr_to_ip(addr: *const libc::sockaddr) -> IpAddr {
+    match i32::from((*addr).sa_family) {
+        libc::AF_INET => {
+            let addr = &*(addr as *const libc::sockaddr_in);
+            IpAddr::V4(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)))
+        }
+        _ => {
+            let addr = &*(addr as *const libc::sockaddr_in6);
+            IpAddr::V6(Ipv6Addr::from(addr.sin6_addr.s6_addr))
+        }
+    }
+}
+
+/// Returns the interface `addr` is directly reachable on, or `None` if it is
+/// behind a router or is one of our own addresses.
+pub fn on_link_interface(addr: &IpAddr) -> Option<Interface> {
+    let interfaces = local_interfaces().ok()?;
+    if interfaces.iter().any(|interface| interface.addr == *addr) {
+        return None;
+    }
+
+    interfaces
+        .into_iter()
+        .find(|interface| interface.contains(addr))
+}
+
+/// Resolves the MAC address of an on-link target, waiting up to `timeout` for an answer.
+pub fn resolve(
+    addr: &IpAddr,
+    interface: &Interface,
+    timeout: Duration,
+) -> io::Result<Option<MacAddr>> {
+    match (addr, interface.addr) {
+        (IpAddr::V4(target), IpAddr::V4(local)) => arp_request(*target, local, interface, timeout),
+        (IpAddr::V6(target), IpAddr::V6(_)) => neighbor_solicitation(*target, interface, timeout),
+        _ => Ok(None),
+    }
+}
+
+/// Broadcasts an ARP request for `target` and waits for the matching reply.
+fn arp_request(
+    target: Ipv4Addr,
+    local: Ipv4Addr,
+    interface: &Interface,
+    timeout: Duration,
+) -> io::Result<Option<MacAddr>> {
+    let protocol = Protocol::from(i32::from(ETH_P_ARP.to_be()));
+    let mut socket = Socket::new(Domain::PACKET, Type::RAW, Some(protocol))?;
+    socket.bind(&link_layer_addr(interface.index, ETH_P_ARP)?)?;
+
+    let mut frame = Vec::with_capacity(42);
+    // Ethernet header
+    frame.extend_from_slice(&[0xff; 6]);
+    frame.extend_from_slice(&interface.mac.0);
+    frame.extend_from_slice(&ETH_P_ARP.to_be_bytes());
+    // ARP request for an IPv4 address over Ethernet
+    frame.extend_from_slice(&[0x00, 0x01, 0x08, 0x00, 6, 4]);
+    frame.extend_from_slice(&ARP_REQUEST.to_be_bytes());
+    frame.extend_from_slice(&interface.mac.0);
+    frame.extend_from_slice(&local.octets());
+    frame.extend_from_slice(&[0; 6]);
+    frame.extend_from_slice(&target.octets());
+
+    socket.send(&frame)?;
+    wait_for_mac(&mut socket, timeout, |frame| {
+        let arp = frame.get(14..42)?;
+        let is_reply = frame[12..14] == ETH_P_ARP.to_be_bytes()
+            && arp[6..8] == ARP_REPLY.to_be_bytes()
+            && arp[14..18] == target.octets();
+        is_reply.then(|| MacAddr(arp[8..14].try_into().unwrap()))
+    })
+}
+
+/// Builds the `sockaddr_ll` address binding a packet socket to one interface and EtherType.
+fn link_layer_addr(index: u32, ethertype: u16) -> io::Result<SockAddr> {
+    let (_, addr) = unsafe {
+        SockAddr::try_init(|storage, len| {
+            let link = &mut *(storage as *mut libc::sockaddr_ll);
+            link.sll_family = libc::AF_PACKET as libc::sa_family_t;
+            link.sll_protocol = ethertype.to_be();
+            link.sll_ifindex = index as libc::c_int;
+            *len = std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+            Ok(())
+        })
+    }?;
+
+    Ok(addr)
+}
+
+/// Sends a Neighbor Solicitation for `target` and waits for the matching advertisement.
+fn neighbor_solicitation(
+    target: Ipv6Addr,
+    interface: &Interface,
+    timeout: Duration,
+) -> io::Result<Option<MacAddr>> {
+    let mut socket = Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::ICMPV6))?;
+    // Neighbor Discovery messages must be sent with a hop limit of 255.
+    socket.set_multicast_hops_v6(255)?;
+    socket.set_unicast_hops_v6(255)?;
+    socket.set_multicast_if_v6(interface.index)?;
+
+    let octets = target.octets();
+    let solicited_node = Ipv6Addr::new(
+        0xff02,
+        0,
+        0,
+        0,
+        0,
+        1,
+        0xff00 | u16::from(octets[13]),
+        u16::from_be_bytes([octets[14], octets[15]]),
+    );
+
+    // The kernel fills in the ICMPv6 checksum on raw ICMPv6 sockets.
+    let mut message = vec![ICMPV6_NEIGHBOR_SOLICITATION, 0, 0, 0, 0, 0, 0, 0];
+    message.extend_from_slice(&octets);
+    message.extend_from_slice(&[ND_OPT_SOURCE_LINK_ADDR, 1]);
+    message.extend_from_slice(&interface.mac.0);
+
+    let dst = SocketAddrV6::new(solicited_node, 0, 0, interface.index);
+    socket.send_to(&message, &SockAddr::from(SocketAddr::V6(dst)))?;
+    wait_for_mac(&mut socket, timeout, |message| {
+        if message.first() != Some(&ICMPV6_NEIGHBOR_ADVERTISEMENT) || message.get(8..24)? != octets
+        {
+            return None;
+        }
+        let mut options = message.get(24..)?;
+        while options.len() >= 8 {
+            let len = usize::from(options[1]) * 8;
+            if len == 0 {
+                break;
+            }
+            if options[0] == ND_OPT_TARGET_LINK_ADDR {
+                return Some(MacAddr(options[2..8].try_into().unwrap()));
+            }
+            options = options.get(len..)?;
+        }
+        None
+    })
+}
+
+/// Reads packets until `parse` extracts a MAC address from one, or `timeout` expires.
+fn wait_for_mac<F>(
+    socket: &mut Socket,
+    timeout: Duration,
+    mut parse: F,
+) -> io::Result<Option<MacAddr>>
+where
+    F: FnMut(&[u8]) -> Option<MacAddr>,
+{
+    let deadline = Instant::now() + timeout;
+    let mut buffer = [0u8; 1500];
+    let mut mac = None;
+    while mac.is_none() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        raw::read_packets(socket, &mut buffer, remaining, |packet| {
+            if mac.is_none() {
+                mac = parse(packet);
+            }
+        })?;
+    }
+
+    Ok(mac)
+}
@@ -0,0 +1,62 @@
+//! ARP and IPv6 Neighbor Discovery for hosts on directly attached networks.
+//!
+//! Firewalls commonly drop ICMP and TCP probes, but a host on the local network
+//! cannot ignore address resolution without cutting itself off from the LAN.
+//! When a target lies in the subnet of one of the local interfaces, discovery
+//! resolves it the way the operating system would:
+//!
+//! - IPv4 targets are sent a broadcast ARP request on the interface
+//! - IPv6 targets are sent a Neighbor Solicitation to their solicited-node
+//!   multicast address
+//!
+//! The answer carries the target's MAC address, whose first three bytes
+//! identify the vendor of the network card through the IEEE OUI registry
+//! embedded from `services/ieee_oui.csv`.
+//!
+//! Both require root privileges or the `CAP_NET_RAW` capability, and are only
+//! available on Linux. On other platforms discovery sends its regular probes
+//! to on-link hosts too, and no MAC address is reported.
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+pub use linux::{on_link_interface, resolve, Interface};
+
+/// Static mapping of IEEE OUI assignments to the organization they belong to.
+/// This mapping is lazily initialized from the embedded CSV file containing the IEEE MA-L registry.
+static OUI_VENDORS: Lazy<HashMap<[u8; 3], &'static str>> = Lazy::new(|| {
+    let oui = include_str!("../../../services/ieee_oui.csv");
+    oui.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let (assignment, vendor) = line.split_once(',')?;
+            let prefix = u32::from_str_radix(assignment, 16).ok()?.to_be_bytes();
+            Some(([prefix[1], prefix[2], prefix[3]], vendor))
+        })
+        .collect()
+});
+
+/// A 48-bit Ethernet hardware address.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    /// Returns the organization the address block was assigned to, if it is registered.
+    pub fn vendor(&self) -> Option<&'static str> {
+        OUI_VENDORS.get(&[self.0[0], self.0[1], self.0[2]]).copied()
+    }
+}
+
+impl Display for MacAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
+            a, b, c, d, e, g
+        )
+    }
+}
@@ -0,0 +1,94 @@
+//! Protocol-specific UDP probe payloads.
+//!
+//! Most UDP services silently drop datagrams they cannot parse, so an empty probe
+//! rarely gets an answer even from an open port. This module holds a table of
+//! valid requests for well-known services, keyed by port. Ports without an entry
+//! are probed with an empty datagram.
+//!
+//! # Example
+//! ```no_run
+//! use pscan::scanners::payloads::payload_for;
+//!
+//! let payload = payload_for(53).unwrap();
+//! assert_eq!(payload.name, "dns");
+//! ```
+
+/// A request that a UDP service is expected to answer.
+#[derive(Debug)]
+pub struct UdpPayload {
+    /// Short name of the protocol the payload speaks
+    pub name: &'static str,
+    /// Ports the payload is sent to
+    pub ports: &'static [u16],
+    /// The datagram contents
+    pub data: &'static [u8],
+}
+
+/// Built-in payloads for well-known UDP services.
+static PAYLOADS: &[UdpPayload] = &[
+    // Standard query for the root name servers
+    UdpPayload {
+        name: "dns",
+        ports: &[53],
+        data: b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x02\x00\x01",
+    },
+    // TFTP read request for a file that is unlikely to exist, answered with an error
+    UdpPayload {
+        name: "tftp",
+        ports: &[69],
+        data: b"\x00\x01pscan.txt\x00octet\x00",
+    },
+    // NTPv4 client mode request
+    UdpPayload {
+        name: "ntp",
+        ports: &[123],
+        data: b"\xe3\x00\x04\xfa\x00\x01\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+                \x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\
+                \x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00",
+    },
+    // NetBIOS node status request for the wildcard name
+    UdpPayload {
+        name: "netbios-ns",
+        ports: &[137],
+        data: b"\x80\xf0\x00\x10\x00\x01\x00\x00\x00\x00\x00\x00\
+                \x20CKAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\x00\x00\x21\x00\x01",
+    },
+    // SNMPv1 GetRequest for sysDescr.0 with the "public" community
+    UdpPayload {
+        name: "snmp",
+        ports: &[161],
+        data: b"\x30\x29\x02\x01\x00\x04\x06public\xa0\x1c\x02\x04\x00\x00\x00\x01\
+                \x02\x01\x00\x02\x01\x00\x30\x0e\x30\x0c\x06\x08\x2b\x06\x01\x02\
+                \x01\x01\x01\x00\x05\x00",
+    },
+    // SSDP search for every device and service
+    UdpPayload {
+        name: "ssdp",
+        ports: &[1900],
+        data: b"M-SEARCH * HTTP/1.1\r\n\
+                HOST: 239.255.255.250:1900\r\n\
+                MAN: \"ssdp:discover\"\r\n\
+                MX: 1\r\n\
+                ST: ssdp:all\r\n\r\n",
+    },
+    // mDNS query for the DNS-SD service enumeration name
+    UdpPayload {
+        name: "mdns",
+        ports: &[5353],
+        data: b"\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+                \x09_services\x07_dns-sd\x04_udp\x05local\x00\x00\x0c\x00\x01",
+    },
+    // Memcached stats command with the UDP frame header
+    UdpPayload {
+        name: "memcached",
+        ports: &[11211],
+        data: b"\x00\x01\x00\x00\x00\x01\x00\x00stats\r\n",
+    },
+];
+
+/// Returns the payload to send to a port, if a well-known service is registered for it.
+pub fn payload_for(port: u16) -> Option<&'static UdpPayload> {
+    PAYLOADS
+        .iter()
+        .find(|payload| payload.ports.contains(&port))
+}
@@ -0,0 +1,347 @@
+//! Port specification parsing.
+//!
+//! A port specification is a comma separated list whose entries may be:
+//!
+//! - Single ports (`22`)
+//! - Ranges (`8000-8100`) including open-ended forms (`-1024`, `60000-`, `-`)
+//! - Service names resolved through the IANA registries (`ssh`, `domain`)
+//!
+//! Entries can be prefixed with a protocol qualifier (`T:` or `U:`) which
+//! applies to every following entry until the next qualifier, so that
+//! `T:22,80,U:53,161` scans TCP ports 22 and 80 and UDP ports 53 and 161.
+//!
+//! Alternatively, [`PortSet::top`] selects the most commonly open ports of each
+//! protocol from the frequency-ranked service files shipped in `services/`.
+//!
+//! # Example
+//! ```no_run
+//! use pscan::scanners::{PortSet, ScanProtocol};
+//!
+//! let ports: PortSet = "ssh,http,8000-8100".parse().unwrap();
+//! assert_eq!(ports.ports(&ScanProtocol::Tcp)[0], 22);
+//! ```
+use crate::results;
+use crate::scanners::ScanProtocol;
+use once_cell::sync::Lazy;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// TCP ports ordered from most to least frequently found open.
+/// This list is lazily initialized from the embedded ranked TCP services CSV file.
+static TCP_RANKED_PORTS: Lazy<Vec<u16>> = Lazy::new(|| {
+    let tcp_services = include_str!("../../services/ranked_tcp_services.csv");
+    tcp_services
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').nth(1).unwrap().parse::<u16>().unwrap())
+        .collect()
+});
+
+/// UDP ports ordered from most to least frequently found open.
+/// This list is lazily initialized from the embedded ranked UDP services CSV file.
+static UDP_RANKED_PORTS: Lazy<Vec<u16>> = Lazy::new(|| {
+    let udp_services = include_str!("../../services/ranked_udp_services.csv");
+    udp_services
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').nth(1).unwrap().parse::<u16>().unwrap())
+        .collect()
+});
+
+/// Represents a range of ports to be scanned.
+#[derive(Debug, Clone)]
+pub struct PortRange {
+    /// The first port number in the range (inclusive)
+    pub start: u16,
+    /// The last port number in the range (inclusive)
+    pub end: u16,
+}
+
+impl PortRange {
+    /// Creates the range of ports from `start` to `end`, both inclusive.
+    pub fn new(start: u16, end: u16) -> Result<Self, String> {
+        if start > end {
+            return Err("Start port must be less than or equal to end port".to_string());
+        }
+
+        Ok(PortRange { start, end })
+    }
+}
+
+impl Default for PortRange {
+    fn default() -> Self {
+        PortRange {
+            start: 1,
+            end: 65535,
+        }
+    }
+}
+
+impl Display for PortRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = String;
+
+    /// Parses a single port, a `start-end` pair or an open-ended range where a
+    /// missing start defaults to 1 and a missing end defaults to 65535.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once('-').unwrap_or((s, s));
+
+        let start = match start {
+            "" => 1,
+            _ => start.parse().map_err(|_| "Invalid start port number")?,
+        };
+        let end = match end {
+            "" => 65535,
+            _ => end.parse().map_err(|_| "Invalid end port number")?,
+        };
+        PortRange::new(start, end)
+    }
+}
+
+/// A set of ports to scan for each protocol.
+///
+/// Ports are kept sorted and free of duplicates. Entries without a protocol
+/// qualifier are added to both the TCP and UDP sets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortSet {
+    tcp: Vec<u16>,
+    udp: Vec<u16>,
+    qualified: bool,
+    top: Option<usize>,
+}
+
+impl PortSet {
+    /// Builds a set of the `n` most commonly open ports for each protocol.
+    ///
+    /// A protocol never gets more ports than are ranked for it, see
+    /// [`PortSet::ranked_len`].
+    pub fn top(n: usize) -> Self {
+        let top_ports = |ranked: &[u16]| {
+            let mut ports: Vec<u16> = ranked.iter().copied().take(n).collect();
+            ports.sort_unstable();
+            ports
+        };
+        let ranked = TCP_RANKED_PORTS.len().max(UDP_RANKED_PORTS.len());
+
+        PortSet {
+            tcp: top_ports(&TCP_RANKED_PORTS),
+            udp: top_ports(&UDP_RANKED_PORTS),
+            qualified: false,
+            top: Some(n.min(ranked)),
+        }
+    }
+
+    /// Returns the number of ports ranked for a protocol, the largest useful
+    /// `n` for [`PortSet::top`].
+    pub fn ranked_len(protocol: &ScanProtocol) -> usize {
+        match protocol {
+            ScanProtocol::Tcp => TCP_RANKED_PORTS.len(),
+            ScanProtocol::Udp => UDP_RANKED_PORTS.len(),
+        }
+    }
+
+    /// Returns the ports to scan for the given protocol.
+    pub fn ports(&self, protocol: &ScanProtocol) -> &[u16] {
+        match protocol {
+            ScanProtocol::Tcp => &self.tcp,
+            ScanProtocol::Udp => &self.udp,
+        }
+    }
+
+    /// Returns true if the specification used `T:`/`U:` protocol qualifiers.
+    pub fn is_qualified(&self) -> bool {
+        self.qualified
+    }
+
+    /// Returns the protocols that have at least one port to scan.
+    pub fn protocols(&self) -> Vec<ScanProtocol> {
+        [ScanProtocol::Tcp, ScanProtocol::Udp]
+            .into_iter()
+            .filter(|protocol| !self.ports(protocol).is_empty())
+            .collect()
+    }
+
+    /// Adds the ports of another set to this one.
+    pub fn extend(&mut self, other: &PortSet) {
+        self.insert(Some(&ScanProtocol::Tcp), &other.tcp);
+        self.insert(Some(&ScanProtocol::Udp), &other.udp);
+        for ports in [&mut self.tcp, &mut self.udp] {
+            ports.sort_unstable();
+            ports.dedup();
+        }
+        self.qualified |= other.qualified;
+        self.top = None;
+    }
+
+    /// Adds the ports registered for a service name to the set.
+    ///
+    /// Without a protocol qualifier the name only needs to be registered for
+    /// one of the protocols.
+    fn insert_service(
+        &mut self,
+        protocol: Option<&ScanProtocol>,
+        name: &str,
+    ) -> Result<(), String> {
+        let protocols = match protocol {
+            Some(protocol) => vec![protocol.clone()],
+            None => vec![ScanProtocol::Tcp, ScanProtocol::Udp],
+        };
+
+        let mut found = false;
+        for protocol in protocols {
+            let ports = results::service_ports(&protocol, name);
+            found |= !ports.is_empty();
+            self.insert(Some(&protocol), &ports);
+        }
+
+        match found {
+            true => Ok(()),
+            false => Err(format!("Unknown service name {}", name)),
+        }
+    }
+
+    fn insert(&mut self, protocol: Option<&ScanProtocol>, ports: &[u16]) {
+        match protocol {
+            Some(ScanProtocol::Tcp) => self.tcp.extend(ports),
+            Some(ScanProtocol::Udp) => self.udp.extend(ports),
+            None => {
+                self.tcp.extend(ports);
+                self.udp.extend(ports);
+            }
+        }
+    }
+}
+
+impl Default for PortSet {
+    fn default() -> Self {
+        PortRange::default().into()
+    }
+}
+
+impl From<PortRange> for PortSet {
+    fn from(range: PortRange) -> Self {
+        let ports: Vec<u16> = (range.start..=range.end).collect();
+        PortSet {
+            tcp: ports.clone(),
+            udp: ports,
+            qualified: false,
+            top: None,
+        }
+    }
+}
+
+impl Display for PortSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(n) = self.top {
+            return write!(f, "top {} ports", n);
+        }
+        if self.tcp == self.udp {
+            return write!(f, "{}", format_ports(&self.tcp));
+        }
+
+        let lists = [("T", &self.tcp), ("U", &self.udp)]
+            .iter()
+            .filter(|(_, ports)| !ports.is_empty())
+            .map(|(qualifier, ports)| format!("{}:{}", qualifier, format_ports(ports)))
+            .collect::<Vec<_>>();
+        write!(f, "{}", lists.join(","))
+    }
+}
+
+impl FromStr for PortSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut set = PortSet {
+            tcp: Vec::new(),
+            udp: Vec::new(),
+            qualified: false,
+            top: None,
+        };
+        let mut protocol = None;
+
+        for entry in s.split(',').map(str::trim) {
+            let entry = match entry.split_once(':') {
+                Some((qualifier, rest)) => {
+                    protocol = match qualifier {
+                        "T" | "t" => Some(ScanProtocol::Tcp),
+                        "U" | "u" => Some(ScanProtocol::Udp),
+                        _ => return Err(format!("Invalid protocol qualifier {}:", qualifier)),
+                    };
+                    set.qualified = true;
+                    rest
+                }
+                None => entry,
+            };
+            if entry.is_empty() {
+                return Err("Empty port list entry".to_string());
+            }
+
+            if entry.chars().all(|c| c.is_ascii_digit() || c == '-') {
+                let range = entry.parse::<PortRange>()?;
+                let ports: Vec<u16> = (range.start..=range.end).collect();
+                set.insert(protocol.as_ref(), &ports);
+            } else {
+                set.insert_service(protocol.as_ref(), entry)?;
+            }
+        }
+
+        for ports in [&mut set.tcp, &mut set.udp] {
+            ports.sort_unstable();
+            ports.dedup();
+        }
+
+        Ok(set)
+    }
+}
+
+/// Formats a sorted port list compactly, collapsing consecutive ports into ranges.
+pub fn format_ports(ports: &[u16]) -> String {
+    let mut ranges: Vec<(u16, u16)> = Vec::new();
+    for &port in ports {
+        match ranges.last_mut() {
+            Some((_, end)) if u32::from(*end) + 1 == u32::from(port) => *end = port,
+            _ => ranges.push((port, port)),
+        }
+    }
+
+    ranges
+        .iter()
+        .map(|(start, end)| match start == end {
+            true => format!("{}", start),
+            false => format!("{}-{}", start, end),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn top_takes_the_highest_ranked_ports() {
+        let ports = PortSet::top(10);
+        let tcp = ports.ports(&ScanProtocol::Tcp);
+        assert_eq!(tcp.len(), 10);
+        assert!(tcp.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(tcp.iter().all(|port| TCP_RANKED_PORTS[..10].contains(port)));
+        assert_eq!(ports.to_string(), "top 10 ports");
+    }
+
+    #[test]
+    fn top_is_capped_at_the_ranked_ports() {
+        let ports = PortSet::top(1000);
+        for protocol in [ScanProtocol::Tcp, ScanProtocol::Udp] {
+            assert_eq!(ports.ports(&protocol).len(), PortSet::ranked_len(&protocol));
+        }
+        let ranked =
+            PortSet::ranked_len(&ScanProtocol::Tcp).max(PortSet::ranked_len(&ScanProtocol::Udp));
+        assert_eq!(ports.to_string(), format!("top {} ports", ranked));
+    }
+}
@@ -0,0 +1,147 @@
+//! Cancellation and progress reporting for running scans.
+//!
+//! A [`CancelToken`] in the [`ScanOptions`](crate::scanners::ScanOptions) lets
+//! another thread stop a scan early. Scanners stop sending new probes once the
+//! token is cancelled and return the results they have so far.
+//!
+//! A [`ProgressTracker`] counts the port results passed through
+//! [`ScanOptions::emit`](crate::scanners::ScanOptions::emit) and calls its
+//! callback with a [`ScanProgress`] after each one.
+//!
+//! # Example
+//! ```no_run
+//! use pscan::scanners::progress::{CancelToken, ProgressTracker};
+//! use pscan::scanners::{PortSet, Scan, ScanOptions, TcpScanner};
+//!
+//! let ports: PortSet = "1-1024".parse().unwrap();
+//! let cancel = CancelToken::new();
+//! let options = ScanOptions {
+//!     cancel: cancel.clone(),
+//!     progress: Some(ProgressTracker::new(1024, |progress| {
+//!         eprintln!("{:.0}% done", progress.percent());
+//!     })),
+//!     ..ScanOptions::default()
+//! };
+//! let results = TcpScanner.scan(&"127.0.0.1".parse().unwrap(), &ports, &options);
+//! ```
+use crate::results::{PortState, ScanResult};
+use std::fmt::{self, Debug};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A flag shared between a scan and whoever may want to stop it.
+///
+/// Clones share the same flag, so cancelling any clone cancels them all.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    /// Asks every scan using this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true once the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A snapshot of how far a scan has come.
+#[derive(Debug, Clone)]
+pub struct ScanProgress {
+    /// The number of ports whose state is known
+    pub ports_done: usize,
+    /// The number of ports the scan will probe in total
+    pub ports_total: usize,
+    /// The number of open ports found so far
+    pub open: usize,
+    /// Time since the tracker was created
+    pub elapsed: Duration,
+}
+
+impl ScanProgress {
+    /// Returns the share of ports done, from 0 to 100.
+    pub fn percent(&self) -> f64 {
+        match self.ports_total {
+            0 => 100.0,
+            total => self.ports_done as f64 * 100.0 / total as f64,
+        }
+    }
+
+    /// Estimates the time until every port is done from the rate so far.
+    ///
+    /// Returns `None` before the first port is done.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.ports_done == 0 {
+            return None;
+        }
+        let remaining = self.ports_total.saturating_sub(self.ports_done);
+        Some(
+            self.elapsed
+                .mul_f64(remaining as f64 / self.ports_done as f64),
+        )
+    }
+}
+
+/// Counts finished ports and reports the progress to a callback.
+///
+/// Clones share the same counters, so one tracker can follow several scans,
+/// e.g. the TCP and UDP scans of every host.
+#[derive(Clone)]
+pub struct ProgressTracker(Arc<TrackerState>);
+
+struct TrackerState {
+    ports_total: usize,
+    ports_done: AtomicUsize,
+    open: AtomicUsize,
+    started: Instant,
+    callback: Box<dyn Fn(&ScanProgress) + Send + Sync>,
+}
+
+impl ProgressTracker {
+    /// Creates a tracker for a scan of `ports_total` ports.
+    pub fn new<F>(ports_total: usize, callback: F) -> Self
+    where
+        F: Fn(&ScanProgress) + Send + Sync + 'static,
+    {
+        ProgressTracker(Arc::new(TrackerState {
+            ports_total,
+            ports_done: AtomicUsize::new(0),
+            open: AtomicUsize::new(0),
+            started: Instant::now(),
+            callback: Box::new(callback),
+        }))
+    }
+
+    /// Counts a finished port and calls the callback with the new progress.
+    pub fn record(&self, result: &ScanResult) {
+        let state = &self.0;
+        let ports_done = state.ports_done.fetch_add(1, Ordering::Relaxed) + 1;
+        let open = match result.state {
+            PortState::Open => state.open.fetch_add(1, Ordering::Relaxed) + 1,
+            _ => state.open.load(Ordering::Relaxed),
+        };
+
+        (state.callback)(&ScanProgress {
+            ports_done,
+            ports_total: state.ports_total,
+            open,
+            elapsed: state.started.elapsed(),
+        });
+    }
+}
+
+impl Debug for ProgressTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressTracker")
+            .field("ports_total", &self.0.ports_total)
+            .field("ports_done", &self.0.ports_done)
+            .finish()
+    }
+}
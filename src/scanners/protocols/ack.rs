@@ -0,0 +1,52 @@
+//! TCP ACK port scanning implementation.
+//!
+//! This module provides the `AckScanner` type, which implements the `Scan` trait by
+//! sending bare ACK segments through a raw socket. An ACK scan does not tell open
+//! from closed ports, instead it maps firewall rules: a port that answers with an
+//! RST is reachable (`unfiltered`) while a port that stays silent is `filtered`,
+//! which is what a stateful firewall does with an ACK outside any connection.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use pscan::scanners::{AckScanner, PortSet, ScanOptions, Scan};
+//! use std::net::IpAddr;
+//!
+//! let scanner = AckScanner;
+//! let addr: IpAddr = "127.0.0.1".parse().unwrap();
+//! let ports: PortSet = "1-1024".parse().unwrap();
+//! let results = scanner.scan(&addr, &ports, &ScanOptions::default());
+//! ```
+//!
+//! # Note
+//!
+//! ACK scans require root privileges or the `CAP_NET_RAW` capability.
+use crate::results::PortState;
+use crate::scanners::raw::{self, TcpReply, TCP_ACK, TCP_RST};
+use crate::scanners::{PortSet, Scan, ScanOptions, ScanProtocol, ScanResults};
+use std::net::IpAddr;
+
+pub struct AckScanner;
+
+impl Scan for AckScanner {
+    /// Performs a TCP ACK scan on the specified IP address on the given set of ports.
+    fn scan(&self, addr: &IpAddr, ports: &PortSet, options: &ScanOptions) -> ScanResults {
+        let ports = ports.ports(&ScanProtocol::Tcp);
+        let mut results = ScanResults::new();
+        if let Err(e) = raw::probe_tcp(addr, ports, options, TCP_ACK, classify_reply, &mut results)
+        {
+            eprintln!("TCP ACK scan failed: {}", e);
+        }
+        results.sort_by_key(|r| r.port);
+
+        results
+    }
+}
+
+/// Maps the reply to an ACK probe to a port state.
+fn classify_reply(reply: Option<&TcpReply>) -> PortState {
+    match reply {
+        Some(reply) if reply.flags & TCP_RST != 0 => PortState::Unfiltered,
+        _ => PortState::Filtered,
+    }
+}
@@ -1,6 +1,12 @@
 //! Protocol-specific scanner implementations.
+pub mod ack;
+pub mod syn;
 pub mod tcp;
 pub mod udp;
+pub mod window;
 
+pub use ack::AckScanner;
+pub use syn::SynScanner;
 pub use tcp::TcpScanner;
 pub use udp::UdpScanner;
+pub use window::WindowScanner;
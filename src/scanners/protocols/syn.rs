@@ -0,0 +1,52 @@
+//! TCP SYN (half-open) port scanning implementation.
+//!
+//! This module provides the `SynScanner` type, which implements the `Scan` trait by
+//! sending crafted SYN segments through a raw socket. A SYN/ACK reply means the
+//! port is open and an RST means it is closed. The handshake is never completed,
+//! the target's SYN/ACK is answered with an RST by the local TCP stack.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use pscan::scanners::{PortSet, ScanOptions, SynScanner, Scan};
+//! use std::net::IpAddr;
+//!
+//! let scanner = SynScanner;
+//! let addr: IpAddr = "127.0.0.1".parse().unwrap();
+//! let ports: PortSet = "1-1024".parse().unwrap();
+//! let results = scanner.scan(&addr, &ports, &ScanOptions::default());
+//! ```
+//!
+//! # Note
+//!
+//! SYN scans require root privileges or the `CAP_NET_RAW` capability.
+use crate::results::PortState;
+use crate::scanners::raw::{self, TcpReply, TCP_ACK, TCP_RST, TCP_SYN};
+use crate::scanners::{PortSet, Scan, ScanOptions, ScanProtocol, ScanResults};
+use std::net::IpAddr;
+
+pub struct SynScanner;
+
+impl Scan for SynScanner {
+    /// Performs a TCP SYN scan on the specified IP address on the given set of ports.
+    fn scan(&self, addr: &IpAddr, ports: &PortSet, options: &ScanOptions) -> ScanResults {
+        let ports = ports.ports(&ScanProtocol::Tcp);
+        let mut results = ScanResults::new();
+        if let Err(e) = raw::probe_tcp(addr, ports, options, TCP_SYN, classify_reply, &mut results)
+        {
+            eprintln!("TCP SYN scan failed: {}", e);
+        }
+        results.sort_by_key(|r| r.port);
+
+        results
+    }
+}
+
+/// Maps the reply to a SYN probe to a port state.
+fn classify_reply(reply: Option<&TcpReply>) -> PortState {
+    match reply {
+        Some(reply) if reply.flags & (TCP_SYN | TCP_ACK) == TCP_SYN | TCP_ACK => PortState::Open,
+        Some(reply) if reply.flags & TCP_RST != 0 => PortState::Closed,
+        _ => PortState::Filtered,
+    }
+}
@@ -4,16 +4,23 @@
 //! which implements the `Scan` trait. It performs TCP connect scans by attempting to
 //! establish full TCP connections to target ports.
 //!
+//! Connections are made with non-blocking sockets driven by a single event loop,
+//! so up to [`ScanOptions::max_parallelism`] connects are in flight at once and a
+//! new connect is started as soon as any outstanding one completes or times out.
+//! Timeouts adapt to the measured round trip time and unanswered connects are
+//! retried, see [`timing`](crate::scanners::timing). Once the scan is cancelled
+//! no new connects are started and the ports found so far are returned.
+//!
 //! # Example
 //!
 //! ```no_run
-//! use pscan::scanners::{PortRange, TcpScanner, Scan};
+//! use pscan::scanners::{PortSet, ScanOptions, TcpScanner, Scan};
 //! use std::net::IpAddr;
 //!
 //! let scanner = TcpScanner;
 //! let addr: IpAddr = "127.0.0.1".parse().unwrap();
-//! let range = PortRange::new(1, 1024);
-//! let results = scanner.scan(&addr, &range, 1000);
+//! let ports: PortSet = "1-1024".parse().unwrap();
+//! let results = scanner.scan(&addr, &ports, &ScanOptions::default());
 //! ```
 //!
 //! # Note
@@ -21,88 +28,205 @@
 //! TCP connect scans are reliable but potentially slower than other scanning
 //! methods due to the full connection establishment process.
 use crate::results::{PortState, ScanResult};
-use crate::scanners::{PortRange, Scan, ScanProtocol, ScanResults};
-use std::net::{TcpStream, ToSocketAddrs};
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
+use crate::scanners::timing::Timing;
+use crate::scanners::{PortSet, Scan, ScanOptions, ScanProtocol, ScanResults};
+use mio::net::TcpStream;
+use mio::{Events, Interest, Poll, Token};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Instant;
 
 pub struct TcpScanner;
 
+/// A connect that has been started but has not completed yet.
+struct PendingConnect {
+    port: u16,
+    attempt: u32,
+    started: Instant,
+    stream: TcpStream,
+}
+
 impl Scan for TcpScanner {
-    /// Performs a TCP port scan on the specified IP address within the given port range.
+    /// Performs a TCP port scan on the specified IP address on the given set of ports.
     ///
-    /// The scan is performed using multiple threads (up to 16).
-    fn scan(
-        &self,
-        addr: &std::net::IpAddr,
-        port_range: &PortRange,
-        timeout_ms: u64,
-    ) -> ScanResults {
-        let ports: Vec<u16> = (port_range.start..=port_range.end).collect();
-        let n_threads = num_cpus::get().min(16);
-        let chunk_size = ports.len().div_ceil(n_threads);
-        let addr = Arc::new(*addr);
-        let results = Arc::new(Mutex::new(ScanResults::new()));
-
-        let handles: Vec<_> = ports
-            .chunks(chunk_size)
-            .enumerate()
-            .map(|(i, chunk)| {
-                let addr = Arc::clone(&addr);
-                let results = Arc::clone(&results);
-                let ports = chunk.to_vec();
-
-                thread::Builder::new()
-                    .name(format!("tcp-scanner-{}", i))
-                    .spawn(move || {
-                        for port in ports {
-                            let target = format!("{}:{}", addr, port);
-                            if let Some(state) = check_tcp_connection(&target, timeout_ms) {
-                                let mut results = results.lock().unwrap();
-                                results.push(ScanResult::new(ScanProtocol::Tcp, port, state));
-                            }
-                        }
-                    })
-                    .expect("Failed to spawn thread")
-            })
-            .collect();
-
-        for handle in handles {
-            if let Err(e) = handle.join() {
-                eprintln!("Thread panicked: {:?}", e);
+    /// Ports are probed by a non-blocking connect engine that keeps up to
+    /// `max_parallelism` connects in flight.
+    fn scan(&self, addr: &IpAddr, ports: &PortSet, options: &ScanOptions) -> ScanResults {
+        let ports = ports.ports(&ScanProtocol::Tcp);
+        let mut results = match run_connect_engine(addr, ports, options) {
+            Ok(results) => results,
+            Err(e) => {
+                eprintln!("TCP connect engine failed: {}", e);
+                return ScanResults::new();
             }
-        }
-
-        let mut results = Arc::try_unwrap(results)
-            .expect("Failed to unwrap Arc")
-            .into_inner()
-            .expect("Failed to acquire mutex lock");
-        results.sort_by(|a, b| a.port.cmp(&b.port));
+        };
+        results.sort_by_key(|r| r.port);
 
         results
     }
 }
 
-/// Attempts to establish a TCP connection to the specified address and determines the port state.
-fn check_tcp_connection<A: ToSocketAddrs>(addr: A, timeout_ms: u64) -> Option<PortState> {
-    let target = match addr.to_socket_addrs() {
-        Ok(mut addrs) => match addrs.next() {
-            Some(addr) => addr,
-            None => {
-                eprintln!("Error: No valid socket address found");
-                return None;
+/// Probes every port with a non-blocking connect, keeping the number of
+/// outstanding connects at or below `max_parallelism`.
+///
+/// Connects are paced and retried on timeout according to the scan's [`Timing`].
+fn run_connect_engine(
+    addr: &IpAddr,
+    ports: &[u16],
+    options: &ScanOptions,
+) -> io::Result<ScanResults> {
+    let timing = Timing::new(options);
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(1024);
+    let mut queue: VecDeque<(u16, u32)> = ports.iter().map(|&port| (port, 0)).collect();
+    let mut in_flight: HashMap<Token, PendingConnect> = HashMap::new();
+    let mut deadlines: BinaryHeap<Reverse<(Instant, Token)>> = BinaryHeap::new();
+    let mut next_token = 0;
+    let mut results = ScanResults::new();
+
+    loop {
+        if options.is_cancelled() {
+            break;
+        }
+        let mut throttle = None;
+        while !queue.is_empty()
+            && (in_flight.len() < options.max_parallelism || timing.below_min_rate())
+        {
+            if let Err(wait) = timing.try_send() {
+                throttle = Some(wait);
+                break;
+            }
+            let (port, attempt) = queue.pop_front().unwrap();
+
+            match TcpStream::connect(SocketAddr::new(*addr, port)) {
+                Ok(mut stream) => {
+                    let token = Token(next_token);
+                    next_token += 1;
+                    poll.registry()
+                        .register(&mut stream, token, Interest::WRITABLE)?;
+                    let started = Instant::now();
+                    deadlines.push(Reverse((started + timing.timeout(attempt), token)));
+                    in_flight.insert(
+                        token,
+                        PendingConnect {
+                            port,
+                            attempt,
+                            started,
+                            stream,
+                        },
+                    );
+                }
+                Err(e) if is_resource_exhausted(&e) && !in_flight.is_empty() => {
+                    // Out of file descriptors, retry once an outstanding connect finishes.
+                    queue.push_front((port, attempt));
+                    break;
+                }
+                Err(e) => {
+                    let (state, reason) = classify_connect_error(&e);
+                    let result = ScanResult::new(ScanProtocol::Tcp, port, state, reason);
+                    options.emit(&result);
+                    results.push(result);
+                }
+            }
+        }
+
+        if in_flight.is_empty() && queue.is_empty() {
+            break;
+        }
+
+        let wait = deadlines
+            .peek()
+            .map(|Reverse((deadline, _))| deadline.saturating_duration_since(Instant::now()));
+        let wait = match (wait, throttle) {
+            (Some(wait), Some(throttle)) => Some(wait.min(throttle)),
+            (wait, throttle) => wait.or(throttle),
+        };
+        match poll.poll(&mut events, wait) {
+            // A signal such as Ctrl-C woke the poll up, check for cancellation.
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => result?,
+        }
+
+        for event in events.iter() {
+            let token = event.token();
+            let Some(pending) = in_flight.get(&token) else {
+                continue;
+            };
+            if let Some((state, reason)) = check_tcp_connection(&pending.stream) {
+                let pending = in_flight.remove(&token).unwrap();
+                let result = ScanResult::new(ScanProtocol::Tcp, pending.port, state, reason);
+                let result = match result.state {
+                    PortState::Filtered => result,
+                    _ => {
+                        let rtt = pending.started.elapsed();
+                        timing.record(pending.attempt, rtt);
+                        result.with_rtt(rtt)
+                    }
+                };
+                options.emit(&result);
+                results.push(result);
+            }
+        }
+
+        let now = Instant::now();
+        while let Some(&Reverse((deadline, token))) = deadlines.peek() {
+            if deadline > now {
+                break;
+            }
+            deadlines.pop();
+            let Some(pending) = in_flight.remove(&token) else {
+                continue;
+            };
+            match timing.should_retry(pending.attempt) {
+                true => queue.push_front((pending.port, pending.attempt + 1)),
+                false => {
+                    let result = ScanResult::new(
+                        ScanProtocol::Tcp,
+                        pending.port,
+                        PortState::Filtered,
+                        "no-response",
+                    );
+                    options.emit(&result);
+                    results.push(result);
+                }
             }
-        },
-        Err(e) => {
-            eprintln!("Error resolving address: {}", e);
-            return None;
         }
-    };
+    }
+
+    Ok(results)
+}
 
-    match TcpStream::connect_timeout(&target, Duration::from_millis(timeout_ms)) {
-        Ok(_) => Some(PortState::Open),
-        Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => Some(PortState::Closed),
-        Err(_) => Some(PortState::Filtered),
+/// Determines the port state of a connect, and the reason for it, once its
+/// socket has become writable.
+///
+/// Returns `None` if the connect is still in progress.
+fn check_tcp_connection(stream: &TcpStream) -> Option<(PortState, &'static str)> {
+    match stream.take_error() {
+        Ok(Some(e)) | Err(e) => return Some(classify_connect_error(&e)),
+        Ok(None) => {}
     }
+
+    match stream.peer_addr() {
+        Ok(_) => Some((PortState::Open, "syn-ack")),
+        Err(e) if e.kind() == io::ErrorKind::NotConnected => None,
+        Err(e) => Some(classify_connect_error(&e)),
+    }
+}
+
+/// Maps a failed connect to a port state and the reason for it.
+fn classify_connect_error(e: &io::Error) -> (PortState, &'static str) {
+    match e.raw_os_error() {
+        Some(libc::ECONNREFUSED) => (PortState::Closed, "conn-refused"),
+        Some(libc::EHOSTUNREACH) => (PortState::Filtered, "host-unreach"),
+        Some(libc::ENETUNREACH) => (PortState::Filtered, "net-unreach"),
+        Some(libc::EACCES) | Some(libc::EPERM) => (PortState::Filtered, "admin-prohibited"),
+        _ => (PortState::Filtered, "no-response"),
+    }
+}
+
+/// Returns true if the error means no more sockets can be opened right now.
+pub fn is_resource_exhausted(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(libc::EMFILE) | Some(libc::ENFILE))
 }
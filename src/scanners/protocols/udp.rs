@@ -1,153 +1,322 @@
 //! UDP port scanning implementation.
 //!
 //! This module provides UDP port scanning functionality through the `UdpScanner` type,
-//! which implements the `Scan` trait. UDP scanning is performed by sending empty
-//! datagrams to target ports and analyzing responses.
+//! which implements the `Scan` trait. UDP scanning is performed by sending datagrams
+//! to target ports and analyzing responses. Ports of well-known services are sent a
+//! valid request from the [`payloads`] table, all other ports are sent an empty
+//! datagram.
+//!
+//! Datagrams are sent from non-blocking sockets driven by a single event loop,
+//! each probe on its own connected socket so that the operating system can
+//! report an ICMP port unreachable on it. Up to 16 probes are in flight at
+//! once, more while the scan is behind `min_rate`, and unanswered probes are
+//! retried, see [`timing`](crate::scanners::timing).
+//!
+//! Ports that never answer are reported as `open|filtered`, since silence is what
+//! both an open port ignoring the probe and a firewall dropping it look like. When
+//! raw socket privileges are available an [`IcmpListener`] classifies ICMP
+//! unreachable replies, otherwise closed ports are only detected where the
+//! operating system reports them as a connection reset on the socket.
 //!
 //! # Limitations
 //!
 //! UDP scanning is less reliable than TCP scanning because:
-//! - Many UDP services don't respond to empty datagrams
+//! - Many UDP services don't respond to empty datagrams or unknown requests
 //! - Responses may be rate-limited by firewalls
 //! - Root/Administrator privileges may be required
 //!
 //! # Example
 //!
 //! ```no_run
-//! use pscan::scanners::{PortRange, UdpScanner, Scan};
+//! use pscan::scanners::{PortSet, ScanOptions, UdpScanner, Scan};
 //! use std::net::IpAddr;
 //!
 //! let scanner = UdpScanner;
 //! let addr: IpAddr = "127.0.0.1".parse().unwrap();
-//! let range = PortRange::new(1, 1024);
-//! let results = scanner.scan(&addr, &range, 1000);
+//! let ports: PortSet = "1-1024".parse().unwrap();
+//! let results = scanner.scan(&addr, &ports, &ScanOptions::default());
 //! ```
 use crate::{
     results::{PortState, ScanResult},
-    scanners::{PortRange, Scan, ScanProtocol, ScanResults},
+    scanners::icmp::IcmpListener,
+    scanners::protocols::tcp::is_resource_exhausted,
+    scanners::timing::Timing,
+    scanners::{payloads, raw, PortSet, Scan, ScanOptions, ScanProtocol, ScanResults},
 };
-use std::net::{ToSocketAddrs, UdpSocket};
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
-use std::{io, net::IpAddr};
+use mio::net::UdpSocket;
+use mio::{Events, Interest, Poll, Token};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use std::{io, net};
+
+/// How often the event loop checks for ICMP verdicts while probes are in flight.
+const ICMP_CHECK_INTERVAL: Duration = Duration::from_millis(5);
+
+/// The number of probes kept in flight unless the scan falls behind `min_rate`.
+const MAX_UDP_IN_FLIGHT: usize = 16;
 
 pub struct UdpScanner;
 
+/// A datagram that has been sent but not answered yet.
+struct PendingProbe {
+    port: u16,
+    attempt: u32,
+    sent: Instant,
+    socket: UdpSocket,
+}
+
 impl Scan for UdpScanner {
-    /// Performs a UDP port scan on the specified IP address within the given port range.
+    /// Performs a UDP port scan on the specified IP address on the given set of ports.
     ///
-    /// The scan is performed using multiple threads (up to 16) to improve performance.
-    fn scan(
-        &self,
-        addr: &std::net::IpAddr,
-        port_range: &PortRange,
-        timeout_ms: u64,
-    ) -> ScanResults {
-        let ports: Vec<u16> = (port_range.start..=port_range.end).collect();
-        let n_threads = num_cpus::get().min(16);
-        let chunk_size = ports.len().div_ceil(n_threads);
-        let target = Arc::new(*addr);
-        let results = Arc::new(Mutex::new(ScanResults::new()));
-
-        let handles: Vec<_> = ports
-            .chunks(chunk_size)
-            .enumerate()
-            .map(|(i, chunk)| {
-                let addr = Arc::clone(&target);
-                let results = Arc::clone(&results);
-                let ports = chunk.to_vec();
-
-                thread::Builder::new()
-                    .name(format!("udp-scanner-{}", i))
-                    .spawn(move || {
-                        let socket = match addr.as_ref() {
-                            IpAddr::V4(_) => UdpSocket::bind("0.0.0.0:0"),
-                            IpAddr::V6(_) => UdpSocket::bind("[::]:0"),
-                        };
-                        let socket = match socket {
-                            Ok(s) => {
-                                if let Err(e) =
-                                    s.set_read_timeout(Some(Duration::from_millis(timeout_ms)))
-                                {
-                                    eprintln!("Failed to set socket read timeout: {}", e);
-                                    return;
-                                }
-                                s
-                            }
-                            Err(e) => {
-                                eprintln!("Failed to bind UDP socket: {}", e);
-                                return;
-                            }
-                        };
-
-                        for port in ports {
-                            let target = format!("{}:{}", addr, port);
-                            if let Some(state) = check_udp_port(&socket, &target) {
-                                let mut results = results.lock().unwrap();
-                                results.push(ScanResult::new(ScanProtocol::Udp, port, state));
-                            }
-                        }
-                    })
-                    .expect("Failed to spawn thread")
-            })
-            .collect();
-
-        for handle in handles {
-            if let Err(e) = handle.join() {
-                eprintln!("Thread panicked: {:?}", e);
-            }
+    /// Up to 16 probes (and no more than `max_parallelism`) are in flight at
+    /// once, more while the scan is behind `min_rate`.
+    fn scan(&self, addr: &IpAddr, ports: &PortSet, options: &ScanOptions) -> ScanResults {
+        let ports = ports.ports(&ScanProtocol::Udp);
+        let mut results = ScanResults::new();
+        if ports.is_empty() {
+            return results;
         }
+        let listener = match raw::has_raw_privileges() {
+            true => IcmpListener::start(*addr)
+                .map_err(|e| eprintln!("Failed to start ICMP listener: {}", e))
+                .ok(),
+            false => None,
+        };
 
-        let mut results = Arc::try_unwrap(results)
-            .expect("Failed to unwrap Arc")
-            .into_inner()
-            .expect("Failed to acquire mutex lock");
-        results.sort_by(|a, b| a.port.cmp(&b.port));
+        if let Err(e) = run_udp_engine(addr, ports, options, listener.as_ref(), &mut results) {
+            eprintln!("UDP scan failed: {}", e);
+        }
+        results.sort_by_key(|r| r.port);
 
         results
     }
 }
 
-/// Checks the state of a UDP port by sending an empty datagram and analyzing the response.
-fn check_udp_port(socket: &UdpSocket, addr: &str) -> Option<PortState> {
-    let target_addr = match addr.to_socket_addrs() {
-        Ok(mut addrs) => match addrs.next() {
-            Some(addr) => addr,
-            None => {
-                eprintln!("Error: No valid socket address found for {}", addr);
-                return None;
+/// Probes every port with a datagram and classifies it from the reply, from an
+/// ICMP verdict, or from the lack of both once the timeout expires.
+///
+/// Each datagram carries the port's well-known service payload if there is one
+/// and is empty otherwise. Datagrams are paced and retransmitted according to
+/// the scan's [`Timing`]. Ports are added to `results` as they are classified,
+/// so they are kept even if the scan fails part way.
+fn run_udp_engine(
+    addr: &IpAddr,
+    ports: &[u16],
+    options: &ScanOptions,
+    listener: Option<&IcmpListener>,
+    results: &mut ScanResults,
+) -> io::Result<()> {
+    let timing = Timing::new(options);
+    let max_in_flight = MAX_UDP_IN_FLIGHT.min(options.max_parallelism);
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(1024);
+    let mut queue: VecDeque<(u16, u32)> = ports.iter().map(|&port| (port, 0)).collect();
+    let mut in_flight: HashMap<Token, PendingProbe> = HashMap::new();
+    let mut deadlines: BinaryHeap<Reverse<(Instant, Token)>> = BinaryHeap::new();
+    let mut next_token = 0;
+    let mut buffer = [0u8; 512];
+
+    loop {
+        if options.is_cancelled() {
+            break;
+        }
+        let mut throttle = None;
+        while !queue.is_empty() && (in_flight.len() < max_in_flight || timing.below_min_rate()) {
+            if let Err(wait) = timing.try_send() {
+                throttle = Some(wait);
+                break;
             }
-        },
-        Err(e) => {
-            eprintln!("Error resolving address {}: {}", addr, e);
-            return None;
+            let (port, attempt) = queue.pop_front().unwrap();
+            let target = SocketAddr::new(*addr, port);
+
+            let mut socket = match open_probe_socket(&target) {
+                Ok(socket) => socket,
+                Err(e) if is_resource_exhausted(&e) && !in_flight.is_empty() => {
+                    // Out of file descriptors, retry once an outstanding probe finishes.
+                    queue.push_front((port, attempt));
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            let payload = payloads::payload_for(port).map(|p| p.data);
+            if socket.send(payload.unwrap_or_default()).is_err() {
+                match timing.should_retry(attempt) {
+                    true => queue.push_back((port, attempt + 1)),
+                    false => push_result(
+                        options,
+                        results,
+                        port,
+                        PortState::Filtered,
+                        "send-error",
+                        None,
+                    ),
+                }
+                continue;
+            }
+
+            let token = Token(next_token);
+            next_token += 1;
+            poll.registry()
+                .register(&mut socket, token, Interest::READABLE)?;
+            let sent = Instant::now();
+            deadlines.push(Reverse((sent + timing.timeout(attempt), token)));
+            in_flight.insert(
+                token,
+                PendingProbe {
+                    port,
+                    attempt,
+                    sent,
+                    socket,
+                },
+            );
         }
-    };
 
-    if let Err(e) = socket.send_to(&[], target_addr) {
-        eprintln!("Error sending UDP packet to {}: {}", addr, e);
-        return None;
-    }
+        if in_flight.is_empty() && queue.is_empty() {
+            break;
+        }
 
-    let mut buffer = [0u8; 512];
-    loop {
-        match socket.recv_from(&mut buffer) {
-            Ok((_, src_addr)) => {
-                // If we receive any data, consider the port Open
-                if src_addr.to_string() == addr {
-                    return Some(PortState::Open);
-                }
+        let wait = deadlines
+            .peek()
+            .map(|Reverse((deadline, _))| deadline.saturating_duration_since(Instant::now()));
+        let wait = match (wait, throttle) {
+            (Some(wait), Some(throttle)) => Some(wait.min(throttle)),
+            (wait, throttle) => wait.or(throttle),
+        };
+        let wait = match listener {
+            Some(_) => Some(wait.map_or(ICMP_CHECK_INTERVAL, |w| w.min(ICMP_CHECK_INTERVAL))),
+            None => wait,
+        };
+        match poll.poll(&mut events, wait) {
+            // A signal such as Ctrl-C woke the poll up, check for cancellation.
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => result?,
+        }
+
+        for event in events.iter() {
+            let token = event.token();
+            let Some(pending) = in_flight.get(&token) else {
+                continue;
+            };
+            let (state, reason) = match pending.socket.recv(&mut buffer) {
+                // The socket is connected, so any data came from the probed port.
+                Ok(_) => (PortState::Open, "udp-response"),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                // ICMP Destination Unreachable reported by the operating system
+                Err(ref e) => classify_recv_error(e),
+            };
+            let pending = in_flight.remove(&token).unwrap();
+            let rtt = pending.sent.elapsed();
+            timing.record(pending.attempt, rtt);
+            push_result(options, results, pending.port, state, reason, Some(rtt));
+        }
+
+        if let Some(listener) = listener {
+            let answered: Vec<Token> = in_flight
+                .iter()
+                .filter(|(_, pending)| listener.verdict(pending.port).is_some())
+                .map(|(&token, _)| token)
+                .collect();
+            for token in answered {
+                let pending = in_flight.remove(&token).unwrap();
+                push_verdict(options, results, listener, &pending);
             }
-            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                // Timeout reached, port is considered Filtered
-                return Some(PortState::Filtered);
+        }
+
+        let now = Instant::now();
+        while let Some(&Reverse((deadline, token))) = deadlines.peek() {
+            if deadline > now {
+                break;
             }
-            Err(ref e) if e.kind() == io::ErrorKind::ConnectionReset => {
-                // ICMP Destination Unreachable received
-                return Some(PortState::Closed);
+            deadlines.pop();
+            let Some(pending) = in_flight.remove(&token) else {
+                continue;
+            };
+            match timing.should_retry(pending.attempt) {
+                true => queue.push_front((pending.port, pending.attempt + 1)),
+                // No reply to any attempt, port is considered Open|Filtered
+                false => push_result(
+                    options,
+                    results,
+                    pending.port,
+                    PortState::OpenFiltered,
+                    "no-response",
+                    None,
+                ),
             }
-            Err(_) => return None, // Handle other unexpected errors
         }
     }
+
+    Ok(())
+}
+
+/// Opens a non-blocking socket connected to the probed port.
+fn open_probe_socket(target: &SocketAddr) -> io::Result<UdpSocket> {
+    let socket = match target {
+        SocketAddr::V4(_) => net::UdpSocket::bind("0.0.0.0:0")?,
+        SocketAddr::V6(_) => net::UdpSocket::bind("[::]:0")?,
+    };
+    socket.connect(target)?;
+    socket.set_nonblocking(true)?;
+
+    Ok(UdpSocket::from_std(socket))
+}
+
+/// Maps an error received on a probe socket, which the operating system
+/// reports for ICMP unreachable messages, to a port state and the reason for it.
+fn classify_recv_error(e: &io::Error) -> (PortState, &'static str) {
+    match e.kind() {
+        io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionRefused => {
+            return (PortState::Closed, "port-unreach")
+        }
+        _ => {}
+    }
+
+    match e.raw_os_error() {
+        Some(libc::EHOSTUNREACH) => (PortState::Filtered, "host-unreach"),
+        Some(libc::ENETUNREACH) => (PortState::Filtered, "net-unreach"),
+        Some(libc::EACCES) | Some(libc::EPERM) => (PortState::Filtered, "admin-prohibited"),
+        _ => (PortState::Filtered, "unreach"),
+    }
+}
+
+/// Records the port state an ICMP message about a pending probe implies.
+fn push_verdict(
+    options: &ScanOptions,
+    results: &mut ScanResults,
+    listener: &IcmpListener,
+    pending: &PendingProbe,
+) {
+    if let Some(verdict) = listener.verdict(pending.port) {
+        let rtt = verdict.received.checked_duration_since(pending.sent);
+        push_result(
+            options,
+            results,
+            pending.port,
+            verdict.state,
+            verdict.reason,
+            rtt,
+        );
+    }
+}
+
+/// Records the result of a port, along with the name of the payload an open
+/// port answered.
+fn push_result(
+    options: &ScanOptions,
+    results: &mut ScanResults,
+    port: u16,
+    state: PortState,
+    reason: &'static str,
+    rtt: Option<Duration>,
+) {
+    let mut result = ScanResult::new(ScanProtocol::Udp, port, state, reason);
+    result.rtt = rtt;
+    let result = match (&result.state, payloads::payload_for(port)) {
+        (PortState::Open, Some(payload)) => result.with_payload(payload.name),
+        _ => result,
+    };
+    options.emit(&result);
+    results.push(result);
 }
@@ -0,0 +1,54 @@
+//! TCP window port scanning implementation.
+//!
+//! This module provides the `WindowScanner` type, which implements the `Scan` trait.
+//! It sends the same bare ACK segments as the ACK scan but also inspects the window
+//! field of the RST replies. Some TCP stacks advertise a non-zero window in RSTs sent
+//! from open ports and a zero window from closed ports, which lets the window scan
+//! tell them apart where an ACK scan only reports `unfiltered`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use pscan::scanners::{PortSet, ScanOptions, WindowScanner, Scan};
+//! use std::net::IpAddr;
+//!
+//! let scanner = WindowScanner;
+//! let addr: IpAddr = "127.0.0.1".parse().unwrap();
+//! let ports: PortSet = "1-1024".parse().unwrap();
+//! let results = scanner.scan(&addr, &ports, &ScanOptions::default());
+//! ```
+//!
+//! # Note
+//!
+//! Window scans require root privileges or the `CAP_NET_RAW` capability. Stacks
+//! that always advertise a zero window make every reachable port look closed.
+use crate::results::PortState;
+use crate::scanners::raw::{self, TcpReply, TCP_ACK, TCP_RST};
+use crate::scanners::{PortSet, Scan, ScanOptions, ScanProtocol, ScanResults};
+use std::net::IpAddr;
+
+pub struct WindowScanner;
+
+impl Scan for WindowScanner {
+    /// Performs a TCP window scan on the specified IP address on the given set of ports.
+    fn scan(&self, addr: &IpAddr, ports: &PortSet, options: &ScanOptions) -> ScanResults {
+        let ports = ports.ports(&ScanProtocol::Tcp);
+        let mut results = ScanResults::new();
+        if let Err(e) = raw::probe_tcp(addr, ports, options, TCP_ACK, classify_reply, &mut results)
+        {
+            eprintln!("TCP window scan failed: {}", e);
+        }
+        results.sort_by_key(|r| r.port);
+
+        results
+    }
+}
+
+/// Maps the reply to an ACK probe to a port state using the RST window size.
+fn classify_reply(reply: Option<&TcpReply>) -> PortState {
+    match reply {
+        Some(reply) if reply.flags & TCP_RST != 0 && reply.window > 0 => PortState::Open,
+        Some(reply) if reply.flags & TCP_RST != 0 => PortState::Closed,
+        _ => PortState::Filtered,
+    }
+}
@@ -0,0 +1,349 @@
+//! Raw socket support for crafted TCP packet scans.
+//!
+//! This module builds bare TCP segments, sends them through a raw socket and
+//! matches the replies coming back from the target. It is shared by every scan
+//! technique that does not use the operating system's TCP stack to connect.
+//!
+//! Raw sockets require root privileges or the `CAP_NET_RAW` capability, use
+//! [`has_raw_privileges`] to check before starting a raw scan.
+use crate::results::{PortState, ScanResult};
+use crate::scanners::timing::Timing;
+use crate::scanners::{ScanOptions, ScanProtocol, ScanResults};
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::cmp::Reverse;
+use std::collections::hash_map::RandomState;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// TCP header flag bits.
+pub const TCP_SYN: u8 = 0x02;
+pub const TCP_RST: u8 = 0x04;
+pub const TCP_ACK: u8 = 0x10;
+
+/// Receive buffer size for raw sockets, large enough to absorb reply bursts.
+const RECV_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+/// Length of a TCP header without options.
+const TCP_HEADER_LEN: usize = 20;
+
+/// How long to pause sending after the kernel reports a full send queue.
+const SEND_BACKOFF: Duration = Duration::from_millis(5);
+
+/// A probe that has been sent but not answered yet.
+struct PendingProbe {
+    attempt: u32,
+    sent: Instant,
+    deadline: Instant,
+}
+
+/// The fields of a TCP segment received from the target that scanners classify on.
+#[derive(Debug, Clone)]
+pub struct TcpReply {
+    /// The port the reply was sent from, i.e. the probed port
+    pub src_port: u16,
+    /// The port the reply was sent to, i.e. our probe's source port
+    pub dst_port: u16,
+    /// The TCP flag bits
+    pub flags: u8,
+    /// The advertised receive window
+    pub window: u16,
+}
+
+/// Returns true if the process may open raw sockets.
+pub fn has_raw_privileges() -> bool {
+    Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::TCP)).is_ok()
+}
+
+/// Returns a random 32-bit value, used for source ports and sequence numbers.
+pub fn random_u32() -> u32 {
+    let mut hasher = RandomState::new().build_hasher();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    hasher.write_u128(now.as_nanos());
+    hasher.write_u32(std::process::id());
+    hasher.finish() as u32
+}
+
+/// Returns the local address the operating system would use to reach `addr`.
+pub fn local_addr_for(addr: &IpAddr) -> io::Result<IpAddr> {
+    let socket = match addr {
+        IpAddr::V4(_) => UdpSocket::bind("0.0.0.0:0")?,
+        IpAddr::V6(_) => UdpSocket::bind("[::]:0")?,
+    };
+    socket.connect(SocketAddr::new(*addr, 9))?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// Opens a raw socket for `protocol` that only receives packets from `addr`.
+pub fn open_raw_socket(addr: &IpAddr, protocol: Protocol) -> io::Result<Socket> {
+    let domain = match addr {
+        IpAddr::V4(_) => Domain::IPV4,
+        IpAddr::V6(_) => Domain::IPV6,
+    };
+    let socket = Socket::new(domain, Type::RAW, Some(protocol))?;
+    socket.connect(&SockAddr::from(SocketAddr::new(*addr, 0)))?;
+    Ok(socket)
+}
+
+/// Computes the Internet checksum over the IP pseudo-header and a transport segment.
+pub fn transport_checksum(src: &IpAddr, dst: &IpAddr, protocol: u8, segment: &[u8]) -> u16 {
+    let mut pseudo = Vec::with_capacity(40);
+    match (src, dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            pseudo.extend_from_slice(&src.octets());
+            pseudo.extend_from_slice(&dst.octets());
+            pseudo.extend_from_slice(&[0, protocol]);
+            pseudo.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+        }
+        (IpAddr::V6(src), IpAddr::V6(dst)) => {
+            pseudo.extend_from_slice(&src.octets());
+            pseudo.extend_from_slice(&dst.octets());
+            pseudo.extend_from_slice(&(segment.len() as u32).to_be_bytes());
+            pseudo.extend_from_slice(&[0, 0, 0, protocol]);
+        }
+        _ => {}
+    }
+
+    internet_checksum(&[&pseudo, segment])
+}
+
+/// Computes the one's complement sum used by IP, ICMP, TCP and UDP checksums.
+pub fn internet_checksum(chunks: &[&[u8]]) -> u16 {
+    let bytes = chunks.iter().flat_map(|chunk| chunk.iter().copied());
+    let mut sum: u32 = 0;
+    let mut high = None;
+    for byte in bytes {
+        match high.take() {
+            None => high = Some(byte),
+            Some(h) => sum += u32::from(u16::from_be_bytes([h, byte])),
+        }
+    }
+    if let Some(h) = high {
+        sum += u32::from(u16::from_be_bytes([h, 0]));
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+/// Builds a TCP segment without options or payload.
+pub fn build_tcp_segment(src: &SocketAddr, dst: &SocketAddr, seq: u32, flags: u8) -> Vec<u8> {
+    let mut segment = vec![0u8; TCP_HEADER_LEN];
+    segment[0..2].copy_from_slice(&src.port().to_be_bytes());
+    segment[2..4].copy_from_slice(&dst.port().to_be_bytes());
+    segment[4..8].copy_from_slice(&seq.to_be_bytes());
+    segment[12] = ((TCP_HEADER_LEN / 4) as u8) << 4;
+    segment[13] = flags;
+    segment[14..16].copy_from_slice(&1024u16.to_be_bytes());
+
+    let checksum = transport_checksum(&src.ip(), &dst.ip(), libc::IPPROTO_TCP as u8, &segment);
+    segment[16..18].copy_from_slice(&checksum.to_be_bytes());
+    segment
+}
+
+/// Returns the transport payload of a packet read from a raw socket.
+///
+/// IPv4 raw sockets deliver the IP header along with the payload while IPv6
+/// raw sockets strip it.
+pub fn strip_ip_header<'a>(addr: &IpAddr, packet: &'a [u8]) -> Option<&'a [u8]> {
+    match addr {
+        IpAddr::V4(_) => {
+            let ihl = usize::from(*packet.first()? & 0x0f) * 4;
+            packet.get(ihl..)
+        }
+        IpAddr::V6(_) => Some(packet),
+    }
+}
+
+/// Parses the TCP header of a packet read from a raw TCP socket.
+pub fn parse_tcp_reply(addr: &IpAddr, packet: &[u8]) -> Option<TcpReply> {
+    let segment = strip_ip_header(addr, packet)?;
+    if segment.len() < TCP_HEADER_LEN {
+        return None;
+    }
+
+    Some(TcpReply {
+        src_port: u16::from_be_bytes([segment[0], segment[1]]),
+        dst_port: u16::from_be_bytes([segment[2], segment[3]]),
+        flags: segment[13],
+        window: u16::from_be_bytes([segment[14], segment[15]]),
+    })
+}
+
+/// Waits up to `wait` for a packet on a raw socket, then hands it and every
+/// other packet already queued on the socket to `handle`.
+pub fn read_packets<F>(
+    socket: &mut Socket,
+    buffer: &mut [u8],
+    wait: Duration,
+    mut handle: F,
+) -> io::Result<()>
+where
+    F: FnMut(&[u8]),
+{
+    socket.set_nonblocking(false)?;
+    socket.set_read_timeout(Some(wait.max(Duration::from_millis(1))))?;
+    loop {
+        match socket.read(buffer) {
+            Ok(n) => handle(&buffer[..n]),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                        | io::ErrorKind::Interrupted
+                ) =>
+            {
+                return Ok(())
+            }
+            Err(e) => return Err(e),
+        }
+        socket.set_nonblocking(true)?;
+    }
+}
+
+/// Sends a bare TCP segment with the given flags to every port and classifies
+/// each port from the reply, or from the lack of one once the timeout expires.
+///
+/// At most `max_parallelism` probes are outstanding at any time, and probes are
+/// paced and retransmitted according to the scan's [`Timing`]. A full send
+/// queue only delays the next probes. Ports are added to `results` as they are
+/// classified, so they are kept even if the scan fails part way.
+pub fn probe_tcp<F>(
+    addr: &IpAddr,
+    ports: &[u16],
+    options: &ScanOptions,
+    flags: u8,
+    classify: F,
+    results: &mut ScanResults,
+) -> io::Result<()>
+where
+    F: Fn(Option<&TcpReply>) -> PortState,
+{
+    let mut socket = open_raw_socket(addr, Protocol::TCP)?;
+    socket.set_recv_buffer_size(RECV_BUFFER_SIZE)?;
+    let src = SocketAddr::new(local_addr_for(addr)?, 40000 + (random_u32() % 20000) as u16);
+    let seq = random_u32();
+    let timing = Timing::new(options);
+
+    let mut queue: VecDeque<(u16, u32)> = ports.iter().map(|&port| (port, 0)).collect();
+    let mut in_flight: HashMap<u16, PendingProbe> = HashMap::new();
+    let mut deadlines: BinaryHeap<Reverse<(Instant, u16)>> = BinaryHeap::new();
+    let mut buffer = [0u8; 1500];
+
+    loop {
+        if options.is_cancelled() {
+            break;
+        }
+        let mut throttle = None;
+        while !queue.is_empty()
+            && (in_flight.len() < options.max_parallelism || timing.below_min_rate())
+        {
+            if let Err(wait) = timing.try_send() {
+                throttle = Some(wait);
+                break;
+            }
+            let (port, attempt) = queue.pop_front().unwrap();
+            let segment = build_tcp_segment(&src, &SocketAddr::new(*addr, port), seq, flags);
+            if let Err(e) = socket.send(&segment) {
+                if !is_send_queue_full(&e) {
+                    return Err(e);
+                }
+                queue.push_front((port, attempt));
+                throttle = Some(SEND_BACKOFF);
+                break;
+            }
+            let sent = Instant::now();
+            let deadline = sent + timing.timeout(attempt);
+            in_flight.insert(
+                port,
+                PendingProbe {
+                    attempt,
+                    sent,
+                    deadline,
+                },
+            );
+            deadlines.push(Reverse((deadline, port)));
+        }
+
+        if in_flight.is_empty() && queue.is_empty() {
+            break;
+        }
+
+        let wait = deadlines
+            .peek()
+            .map(|Reverse((deadline, _))| deadline.saturating_duration_since(Instant::now()));
+        let wait = match (wait, throttle) {
+            (Some(wait), Some(throttle)) => wait.min(throttle),
+            (wait, throttle) => wait.or(throttle).unwrap_or_default(),
+        };
+        read_packets(&mut socket, &mut buffer, wait, |packet| {
+            let Some(reply) = parse_tcp_reply(addr, packet) else {
+                return;
+            };
+            if reply.dst_port != src.port() {
+                return;
+            }
+            if let Some(probe) = in_flight.remove(&reply.src_port) {
+                let rtt = probe.sent.elapsed();
+                timing.record(probe.attempt, rtt);
+                let state = classify(Some(&reply));
+                let reason = reply_reason(Some(&reply));
+                let result =
+                    ScanResult::new(ScanProtocol::Tcp, reply.src_port, state, reason).with_rtt(rtt);
+                options.emit(&result);
+                results.push(result);
+            }
+        })?;
+
+        let now = Instant::now();
+        while let Some(&Reverse((deadline, port))) = deadlines.peek() {
+            if deadline > now {
+                break;
+            }
+            deadlines.pop();
+            // Skip deadlines of probes that were answered or have been retransmitted since.
+            let Some(probe) = in_flight.get(&port).filter(|p| p.deadline == deadline) else {
+                continue;
+            };
+            let attempt = probe.attempt;
+            in_flight.remove(&port);
+            match timing.should_retry(attempt) {
+                true => queue.push_front((port, attempt + 1)),
+                false => {
+                    let result = ScanResult::new(
+                        ScanProtocol::Tcp,
+                        port,
+                        classify(None),
+                        reply_reason(None),
+                    );
+                    options.emit(&result);
+                    results.push(result);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns true if a send failed only because the socket or interface queue is full.
+fn is_send_queue_full(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::WouldBlock || e.raw_os_error() == Some(libc::ENOBUFS)
+}
+
+/// Returns the reason a port state was derived from a TCP reply, or from the lack of one.
+fn reply_reason(reply: Option<&TcpReply>) -> &'static str {
+    match reply {
+        Some(reply) if reply.flags & (TCP_SYN | TCP_ACK) == TCP_SYN | TCP_ACK => "syn-ack",
+        Some(reply) if reply.flags & TCP_RST != 0 => "reset",
+        Some(_) => "tcp-response",
+        None => "no-response",
+    }
+}
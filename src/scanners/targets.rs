@@ -0,0 +1,459 @@
+//! Target specification parsing and expansion.
+//!
+//! A target specification given on the command line can name a single host or
+//! a whole group of hosts:
+//!
+//! - An IP address (`10.0.0.1`, `fd00::1`)
+//! - A hostname (`example.com`)
+//! - A CIDR block (`10.0.0.0/24`, `fd00::/120`)
+//! - An IPv4 octet range (`10.0.0.1-50`, `10.0.1-3.1-254`)
+//!
+//! Every [`Address`] expands into one or more [`Target`]s which together form
+//! the [`TargetSet`] iterated by the scanners. Addresses can also be read from
+//! list files (one or more specifications per line, `#` starts a comment) and
+//! hosts can be left out of the scan with an [`ExcludeList`].
+//!
+//! # Example
+//! ```no_run
+//! use pscan::scanners::Address;
+//!
+//! let addr: Address = "10.0.0.0/30".parse().unwrap();
+//! let targets = addr.targets().unwrap();
+//! assert_eq!(targets.len(), 4);
+//! ```
+use crate::utils;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs};
+use std::str::FromStr;
+
+/// The largest number of host bits a CIDR block or octet range may expand to
+/// (a /12 for IPv4).
+const MAX_HOST_BITS: u32 = 20;
+
+/// Represents a network address that can be either an IP address or a hostname
+#[derive(Debug, Clone)]
+pub enum Address {
+    /// An IP address (either IPv4 or IPv6)
+    Ip(IpAddr),
+    /// A domain name or hostname as a string
+    Hostname(String),
+    /// A network in CIDR notation, stored as its base address and prefix length
+    Cidr(IpAddr, u8),
+    /// An IPv4 range given as inclusive `(start, end)` bounds for each octet
+    Range([(u8, u8); 4]),
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Address::Ip(ip) => write!(f, "{}", ip),
+            Address::Hostname(hostname) => write!(f, "{}", hostname),
+            Address::Cidr(base, prefix) => write!(f, "{}/{}", base, prefix),
+            Address::Range(octets) => {
+                let octets = octets
+                    .iter()
+                    .map(|(start, end)| match start == end {
+                        true => format!("{}", start),
+                        false => format!("{}-{}", start, end),
+                    })
+                    .collect::<Vec<_>>();
+                write!(f, "{}", octets.join("."))
+            }
+        }
+    }
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        if let Some((base, prefix)) = input.split_once('/') {
+            return parse_cidr(base, prefix);
+        }
+
+        if let Ok(ip) = input.parse::<IpAddr>() {
+            Ok(Address::Ip(ip))
+        } else if is_octet_range(input) {
+            parse_octet_range(input)
+        } else if is_valid_hostname(input) {
+            Ok(Address::Hostname(input.to_string()))
+        } else {
+            Err(format!("Invalid IP address or hostname {}", input))
+        }
+    }
+}
+
+impl Address {
+    /// Expands the address into the individual hosts it covers.
+    ///
+    /// Hostnames are resolved to their first IP address. An error is returned
+    /// if a hostname cannot be resolved.
+    pub fn targets(&self) -> Result<TargetSet, String> {
+        match self {
+            Address::Ip(ip) => Ok(vec![Target::new(*ip)]),
+            Address::Hostname(hostname) => utils::resolve_hostname_to_ip(hostname)
+                .map(|ip| vec![Target::with_hostname(hostname, ip)])
+                .ok_or(format!("Could not resolve hostname {}", hostname)),
+            Address::Cidr(IpAddr::V4(base), prefix) => {
+                let host_bits = 32 - u32::from(*prefix);
+                let network = u32::from(*base) & u32::MAX.checked_shl(host_bits).unwrap_or(0);
+                Ok((0..1u64 << host_bits)
+                    .map(|i| Target::new(IpAddr::V4(Ipv4Addr::from(network + i as u32))))
+                    .collect())
+            }
+            Address::Cidr(IpAddr::V6(base), prefix) => {
+                let host_bits = 128 - u32::from(*prefix);
+                let network = u128::from(*base) & u128::MAX.checked_shl(host_bits).unwrap_or(0);
+                Ok((0..1u128 << host_bits)
+                    .map(|i| Target::new(IpAddr::V6(Ipv6Addr::from(network + i))))
+                    .collect())
+            }
+            Address::Range([a, b, c, d]) => {
+                let mut targets = TargetSet::new();
+                for o1 in a.0..=a.1 {
+                    for o2 in b.0..=b.1 {
+                        for o3 in c.0..=c.1 {
+                            for o4 in d.0..=d.1 {
+                                let ip = Ipv4Addr::new(o1, o2, o3, o4);
+                                targets.push(Target::new(IpAddr::V4(ip)));
+                            }
+                        }
+                    }
+                }
+                Ok(targets)
+            }
+        }
+    }
+
+    /// Returns true if the given IP address is covered by this address.
+    ///
+    /// Hostnames are only compared by name, see [`ExcludeList`] for matching
+    /// against their resolved address.
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self, ip) {
+            (Address::Ip(addr), ip) => addr == ip,
+            (Address::Hostname(_), _) => false,
+            (Address::Cidr(IpAddr::V4(base), prefix), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(*prefix)).unwrap_or(0);
+                u32::from(*base) & mask == u32::from(*ip) & mask
+            }
+            (Address::Cidr(IpAddr::V6(base), prefix), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(*prefix)).unwrap_or(0);
+                u128::from(*base) & mask == u128::from(*ip) & mask
+            }
+            (Address::Range(octets), IpAddr::V4(ip)) => octets
+                .iter()
+                .zip(ip.octets())
+                .all(|((start, end), octet)| (*start..=*end).contains(&octet)),
+            _ => false,
+        }
+    }
+}
+
+pub fn parse_addr(input: &str) -> Result<Address, String> {
+    input.parse::<Address>()
+}
+
+/// Parses the `base/prefix` halves of a CIDR block.
+fn parse_cidr(base: &str, prefix: &str) -> Result<Address, String> {
+    let base = base
+        .parse::<IpAddr>()
+        .map_err(|_| format!("Invalid CIDR base address {}", base))?;
+    let prefix = prefix
+        .parse::<u8>()
+        .map_err(|_| format!("Invalid CIDR prefix length {}", prefix))?;
+
+    let max_prefix = match base {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    };
+    if prefix > max_prefix {
+        return Err(format!(
+            "CIDR prefix length must be at most {} for {}",
+            max_prefix, base
+        ));
+    }
+    if u32::from(max_prefix - prefix) > MAX_HOST_BITS {
+        return Err(format!(
+            "CIDR block {}/{} is too large, at most {} host bits are supported",
+            base, prefix, MAX_HOST_BITS
+        ));
+    }
+
+    Ok(Address::Cidr(base, prefix))
+}
+
+/// Returns true if the input is a syntactically valid hostname.
+///
+/// All-numeric names are rejected so that malformed IPv4 addresses such as
+/// `10.0.0.300` are reported instead of being looked up in DNS.
+fn is_valid_hostname(input: &str) -> bool {
+    let labels: Vec<&str> = input.trim_end_matches('.').split('.').collect();
+    input.len() <= 253
+        && !labels.iter().all(|l| l.chars().all(|c| c.is_ascii_digit()))
+        && labels.iter().all(|l| {
+            !l.is_empty()
+                && l.len() <= 63
+                && !l.starts_with('-')
+                && !l.ends_with('-')
+                && l.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+}
+
+/// Returns true if the input looks like an IPv4 address with at least one octet range.
+fn is_octet_range(input: &str) -> bool {
+    let parts: Vec<&str> = input.split('.').collect();
+    parts.len() == 4
+        && input.contains('-')
+        && parts
+            .iter()
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit() || c == '-'))
+}
+
+/// Parses an IPv4 octet range such as `10.0.1-3.1-254`.
+fn parse_octet_range(input: &str) -> Result<Address, String> {
+    let mut octets = [(0u8, 0u8); 4];
+    for (octet, part) in octets.iter_mut().zip(input.split('.')) {
+        let (start, end) = part.split_once('-').unwrap_or((part, part));
+        let start = start
+            .parse::<u8>()
+            .map_err(|_| format!("Invalid octet range {} in {}", part, input))?;
+        let end = end
+            .parse::<u8>()
+            .map_err(|_| format!("Invalid octet range {} in {}", part, input))?;
+        if start > end {
+            return Err(format!(
+                "Octet range start must be less than or equal to end in {}",
+                input
+            ));
+        }
+        *octet = (start, end);
+    }
+    let hosts: u64 = octets
+        .iter()
+        .map(|(start, end)| u64::from(end - start) + 1)
+        .product();
+    if hosts > 1 << MAX_HOST_BITS {
+        return Err(format!(
+            "Octet range {} is too large, at most {} host bits are supported",
+            input, MAX_HOST_BITS
+        ));
+    }
+
+    Ok(Address::Range(octets))
+}
+
+/// A single host to be scanned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    /// The hostname the target was specified by, if any
+    pub hostname: Option<String>,
+    /// The IP address that is probed
+    pub ip: IpAddr,
+}
+
+impl Target {
+    pub fn new(ip: IpAddr) -> Self {
+        Target { hostname: None, ip }
+    }
+
+    pub fn with_hostname(hostname: &str, ip: IpAddr) -> Self {
+        Target {
+            hostname: Some(hostname.to_string()),
+            ip,
+        }
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.hostname {
+            Some(ref hostname) => write!(f, "{} ({})", hostname, self.ip),
+            None => write!(f, "{}", self.ip),
+        }
+    }
+}
+
+/// The set of hosts produced by expanding one or more [`Address`]es.
+pub type TargetSet = Vec<Target>;
+
+/// Reads target specifications from a list file, or from stdin if `path` is `-`.
+///
+/// Each line may hold several whitespace or comma separated specifications and
+/// anything after a `#` is ignored. Lines that fail to parse do not abort the
+/// read, they are returned as errors tagged with their line number instead.
+pub fn read_address_list(path: &str) -> io::Result<(Vec<Address>, Vec<String>)> {
+    let reader: Box<dyn BufRead> = match path {
+        "-" => Box::new(BufReader::new(io::stdin())),
+        _ => Box::new(BufReader::new(File::open(path)?)),
+    };
+
+    let mut addrs = Vec::new();
+    let mut errors = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.split('#').next().unwrap_or_default();
+        for spec in line
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|s| !s.is_empty())
+        {
+            match spec.parse::<Address>() {
+                Ok(addr) => addrs.push(addr),
+                Err(e) => errors.push(format!("{}:{}: {}", path, i + 1, e)),
+            }
+        }
+    }
+
+    Ok((addrs, errors))
+}
+
+/// A set of addresses that must never be scanned.
+#[derive(Debug, Default)]
+pub struct ExcludeList {
+    addrs: Vec<Address>,
+    resolved: Vec<IpAddr>,
+}
+
+impl ExcludeList {
+    /// Builds an exclusion list, resolving any hostnames up front.
+    ///
+    /// Every address a hostname resolves to is excluded. Hostnames that cannot
+    /// be resolved are still matched by name.
+    pub fn new(addrs: Vec<Address>) -> Self {
+        let resolved = addrs
+            .iter()
+            .flat_map(|addr| match addr {
+                Address::Hostname(hostname) => (hostname.as_str(), 0)
+                    .to_socket_addrs()
+                    .map(|addrs| addrs.map(|addr| addr.ip()).collect())
+                    .unwrap_or_default(),
+                _ => Vec::new(),
+            })
+            .collect();
+
+        ExcludeList { addrs, resolved }
+    }
+
+    /// Returns true if the target matches any excluded address.
+    pub fn contains(&self, target: &Target) -> bool {
+        self.resolved.contains(&target.ip)
+            || self.addrs.iter().any(|addr| match addr {
+                Address::Hostname(hostname) => target.hostname.as_ref() == Some(hostname),
+                _ => addr.contains(&target.ip),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn ips(targets: TargetSet) -> Vec<String> {
+        targets.iter().map(|target| target.ip.to_string()).collect()
+    }
+
+    #[test]
+    fn valid_addresses_parse() {
+        let cases = [
+            "10.0.0.1",
+            "fd00::1",
+            "10.0.0.0/24",
+            "10.0.0.0/12",
+            "10.0.0.1/32",
+            "fd00::/120",
+            "fd00::/108",
+            "10.0.0.1-50",
+            "10.0.1-3.1-254",
+            "10.0-15.0-255.0-255",
+            "example.com",
+            "host_1.local",
+        ];
+        for input in cases {
+            let addr = input.parse::<Address>();
+            assert!(addr.is_ok(), "{}: {:?}", input, addr);
+            assert_eq!(addr.unwrap().to_string(), input);
+        }
+    }
+
+    #[test]
+    fn invalid_addresses_are_rejected() {
+        let cases = [
+            ("10.0.0.0/33", "CIDR prefix length must be at most 32"),
+            ("fd00::/129", "CIDR prefix length must be at most 128"),
+            ("10.0.0.0/8", "CIDR block 10.0.0.0/8 is too large"),
+            ("fd00::/64", "CIDR block fd00::/64 is too large"),
+            ("10.0.0.0/x", "Invalid CIDR prefix length x"),
+            ("example/24", "Invalid CIDR base address example"),
+            (
+                "0-255.0-255.0-255.0-255",
+                "Octet range 0-255.0-255.0-255.0-255 is too large",
+            ),
+            ("10.0-16.0-255.0-255", "is too large"),
+            (
+                "10.0.0.50-1",
+                "Octet range start must be less than or equal",
+            ),
+            ("10.0.0.1-256", "Invalid octet range 1-256"),
+            ("10.0.0.300", "Invalid IP address or hostname"),
+            ("-bad.example", "Invalid IP address or hostname"),
+            ("", "Invalid IP address or hostname"),
+        ];
+        for (input, error) in cases {
+            match input.parse::<Address>() {
+                Ok(addr) => panic!("{} parsed as {}", input, addr),
+                Err(e) => assert!(e.contains(error), "{}: {}", input, e),
+            }
+        }
+    }
+
+    #[test]
+    fn cidr_blocks_expand_from_their_network_address() {
+        let addr: Address = "10.0.0.5/30".parse().unwrap();
+        assert_eq!(
+            ips(addr.targets().unwrap()),
+            ["10.0.0.4", "10.0.0.5", "10.0.0.6", "10.0.0.7"]
+        );
+        let addr: Address = "fd00::/127".parse().unwrap();
+        assert_eq!(ips(addr.targets().unwrap()), ["fd00::", "fd00::1"]);
+    }
+
+    #[test]
+    fn octet_ranges_expand_every_combination() {
+        let addr: Address = "10.0.1-2.1-2".parse().unwrap();
+        assert_eq!(
+            ips(addr.targets().unwrap()),
+            ["10.0.1.1", "10.0.1.2", "10.0.2.1", "10.0.2.2"]
+        );
+    }
+
+    #[test]
+    fn exclude_list_matches_addresses_blocks_ranges_and_names() {
+        let addrs = ["10.0.0.0/30", "10.0.1.1-3", "fd00::1", "localhost"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect();
+        let excluded = ExcludeList::new(addrs);
+
+        let cases = [
+            (Target::new(ip("10.0.0.3")), true),
+            (Target::new(ip("10.0.0.4")), false),
+            (Target::new(ip("10.0.1.2")), true),
+            (Target::new(ip("10.0.1.4")), false),
+            (Target::new(ip("fd00::1")), true),
+            (Target::new(ip("fd00::2")), false),
+            (Target::new(ip("127.0.0.1")), true),
+            (Target::with_hostname("localhost", ip("10.9.9.9")), true),
+            (Target::with_hostname("example.com", ip("10.9.9.9")), false),
+        ];
+        for (target, expected) in cases {
+            assert_eq!(excluded.contains(&target), expected, "{:?}", target);
+        }
+    }
+}
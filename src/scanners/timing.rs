@@ -0,0 +1,238 @@
+//! Probe timeouts and retransmissions shared by all scanners.
+//!
+//! Each scan keeps a [`Timing`] that estimates the round trip time to the target
+//! the same way TCP computes its retransmission timeout (RFC 6298): a smoothed
+//! RTT and RTT variance are updated from every answered probe and the probe
+//! timeout is `srtt + 4 * rttvar`, clamped to the configured bounds. The
+//! estimate is seeded from the host discovery ping when one is available.
+//!
+//! Probes that time out are retransmitted up to `max_retries` times, each retry
+//! doubling the timeout. Following Karn's algorithm, replies to retransmitted
+//! probes are not used as RTT samples since they cannot be matched to a send.
+//!
+//! [`Timing`] also paces transmissions: every probe, retransmissions included,
+//! must be cleared with it before it is sent. Probes are spaced at least
+//! `scan_delay_ms` apart and never sent faster than `max_rate` per second,
+//! while `min_rate` lets scanners exceed `max_parallelism` when they fall
+//! behind. A [`TimingTemplate`] sets all of these together.
+use crate::scanners::{CancelToken, ScanOptions};
+use clap::ValueEnum;
+use std::fmt::Display;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Named sets of timing options, from slowest and stealthiest to fastest.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum TimingTemplate {
+    /// One probe every five minutes, for evading intrusion detection
+    #[value(alias = "0")]
+    Paranoid,
+    /// One probe every 15 seconds
+    #[value(alias = "1")]
+    Sneaky,
+    /// One probe every 400 ms, to go easy on fragile devices and links
+    #[value(alias = "2")]
+    Polite,
+    /// The default timing
+    #[value(alias = "3")]
+    Normal,
+    /// More parallelism and a tighter timeout cap, for fast reliable networks
+    #[value(alias = "4")]
+    Aggressive,
+    /// Maximum speed at the cost of accuracy
+    #[value(alias = "5")]
+    Insane,
+}
+
+impl Display for TimingTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let template = match self {
+            TimingTemplate::Paranoid => "paranoid",
+            TimingTemplate::Sneaky => "sneaky",
+            TimingTemplate::Polite => "polite",
+            TimingTemplate::Normal => "normal",
+            TimingTemplate::Aggressive => "aggressive",
+            TimingTemplate::Insane => "insane",
+        };
+
+        write!(f, "{}", template)
+    }
+}
+
+impl From<TimingTemplate> for ScanOptions {
+    fn from(template: TimingTemplate) -> Self {
+        // (parallelism, scan delay, initial timeout, min timeout, max timeout, retries)
+        let (max_parallelism, scan_delay_ms, timeout_ms, min_rtt, max_rtt, max_retries) =
+            match template {
+                TimingTemplate::Paranoid => (1, 300_000, 5000, 100, 10_000, 5),
+                TimingTemplate::Sneaky => (1, 15_000, 5000, 100, 10_000, 5),
+                TimingTemplate::Polite => (1, 400, 1000, 100, 10_000, 5),
+                TimingTemplate::Normal => (1024, 0, 25, 10, 1000, 1),
+                TimingTemplate::Aggressive => (2048, 0, 25, 10, 500, 1),
+                TimingTemplate::Insane => (4096, 0, 10, 5, 250, 0),
+            };
+
+        ScanOptions {
+            timeout_ms,
+            max_parallelism,
+            max_retries,
+            min_rtt_timeout_ms: min_rtt,
+            max_rtt_timeout_ms: max_rtt,
+            initial_rtt: None,
+            scan_delay_ms,
+            min_rate: None,
+            max_rate: None,
+            result_sink: None,
+            progress: None,
+            cancel: CancelToken::default(),
+        }
+    }
+}
+
+/// Smoothed round trip time estimator.
+#[derive(Debug, Clone)]
+pub struct RttEstimator {
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    initial: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl RttEstimator {
+    pub fn new(initial: Duration, min: Duration, max: Duration) -> Self {
+        RttEstimator {
+            srtt: None,
+            rttvar: Duration::ZERO,
+            initial,
+            min,
+            max,
+        }
+    }
+
+    /// Folds a new round trip time sample into the estimate.
+    pub fn update(&mut self, sample: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(sample);
+                self.rttvar = sample / 2;
+            }
+            Some(srtt) => {
+                self.rttvar = (self.rttvar * 3 + srtt.abs_diff(sample)) / 4;
+                self.srtt = Some((srtt * 7 + sample) / 8);
+            }
+        }
+    }
+
+    /// Returns the current probe timeout.
+    pub fn timeout(&self) -> Duration {
+        let timeout = match self.srtt {
+            Some(srtt) => srtt + self.rttvar * 4,
+            None => self.initial,
+        };
+        timeout.clamp(self.min, self.max)
+    }
+}
+
+/// Transmission bookkeeping used to enforce the rate limits.
+#[derive(Debug)]
+struct Pacing {
+    started: Instant,
+    next_send: Instant,
+    sent: u64,
+}
+
+/// Timeout, retransmission and rate policy for one scan, safe to share between threads.
+#[derive(Debug)]
+pub struct Timing {
+    estimator: Mutex<RttEstimator>,
+    max_retries: u32,
+    max: Duration,
+    interval: Duration,
+    min_rate: Option<f64>,
+    pacing: Mutex<Pacing>,
+}
+
+impl Timing {
+    /// Builds the timing state for a scan, seeded from the discovery RTT if known.
+    pub fn new(options: &ScanOptions) -> Self {
+        let min = Duration::from_millis(options.min_rtt_timeout_ms);
+        let max = Duration::from_millis(options.max_rtt_timeout_ms);
+        let mut estimator = RttEstimator::new(Duration::from_millis(options.timeout_ms), min, max);
+        if let Some(rtt) = options.initial_rtt {
+            estimator.update(rtt);
+        }
+
+        let rate_interval = options
+            .max_rate
+            .map(|rate| Duration::from_secs_f64(1.0 / rate))
+            .unwrap_or_default();
+        let now = Instant::now();
+
+        Timing {
+            estimator: Mutex::new(estimator),
+            max_retries: options.max_retries,
+            max,
+            interval: rate_interval.max(Duration::from_millis(options.scan_delay_ms)),
+            min_rate: options.min_rate,
+            pacing: Mutex::new(Pacing {
+                started: now,
+                next_send: now,
+                sent: 0,
+            }),
+        }
+    }
+
+    /// Claims the right to send one probe now.
+    ///
+    /// Returns how long to wait before asking again if the rate limits do not
+    /// allow a probe to be sent yet.
+    pub fn try_send(&self) -> Result<(), Duration> {
+        let mut pacing = self.pacing.lock().unwrap();
+        let now = Instant::now();
+        if now < pacing.next_send {
+            return Err(pacing.next_send - now);
+        }
+        pacing.next_send = now + self.interval;
+        pacing.sent += 1;
+        Ok(())
+    }
+
+    /// Blocks until the rate limits allow one probe to be sent.
+    pub fn wait_send(&self) {
+        while let Err(wait) = self.try_send() {
+            thread::sleep(wait);
+        }
+    }
+
+    /// Returns true if fewer probes have been sent than `min_rate` requires,
+    /// in which case scanners may exceed `max_parallelism` to catch up.
+    pub fn below_min_rate(&self) -> bool {
+        let Some(min_rate) = self.min_rate else {
+            return false;
+        };
+        let pacing = self.pacing.lock().unwrap();
+        (pacing.sent as f64) < pacing.started.elapsed().as_secs_f64() * min_rate
+    }
+
+    /// Returns the timeout for a probe, doubling it for every retransmission.
+    pub fn timeout(&self, attempt: u32) -> Duration {
+        let timeout = self.estimator.lock().unwrap().timeout();
+        timeout.saturating_mul(1 << attempt.min(16)).min(self.max)
+    }
+
+    /// Records the round trip time of an answered probe.
+    ///
+    /// Samples from retransmitted probes are ignored.
+    pub fn record(&self, attempt: u32, rtt: Duration) {
+        if attempt == 0 {
+            self.estimator.lock().unwrap().update(rtt);
+        }
+    }
+
+    /// Returns true if a probe that timed out on `attempt` should be sent again.
+    pub fn should_retry(&self, attempt: u32) -> bool {
+        attempt < self.max_retries
+    }
+}
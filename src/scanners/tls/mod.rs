@@ -0,0 +1,275 @@
+//! TLS inspection of open TCP ports.
+//!
+//! After the port scan of a host, [`inspect_ports`] completes a TLS handshake
+//! with each of its open TCP ports and records the negotiated protocol
+//! version, cipher suite and ALPN protocol, and the subject, subject
+//! alternative names, issuer, validity period and SHA-256 fingerprint of the
+//! server's certificate. Ports that do not answer the handshake get nothing.
+//!
+//! Ports registered for a service that starts in plaintext, such as SMTP,
+//! IMAP, POP3, FTP, XMPP and PostgreSQL, are first asked to switch to TLS
+//! with the request of their protocol, see [`StartTls`].
+//!
+//! The certificate is never verified, so that expired, self-signed and
+//! mismatched certificates are recorded like any other. TLS 1.2 and 1.3 are
+//! offered; ports that only speak older versions fail the handshake.
+//!
+//! # Example
+//! ```no_run
+//! use pscan::scanners::tls;
+//! use std::time::Duration;
+//!
+//! let addr = "127.0.0.1:443".parse().unwrap();
+//! if let Ok(info) = tls::inspect(&addr, None, None, Duration::from_secs(3)) {
+//!     println!("{} {}", info.version, info.cipher_suite);
+//!     if let Some(cert) = info.certificate {
+//!         println!("{} expires in {} days", cert.subject, cert.days_left());
+//!     }
+//! }
+//! ```
+use crate::results::{CertificateInfo, PortState, ScanResult, TlsInfo};
+use crate::scanners::{banner, ScanOptions, ScanProtocol};
+use once_cell::sync::Lazy;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{self, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, ProtocolVersion};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use x509_parser::extensions::GeneralName;
+
+mod starttls;
+
+pub use starttls::StartTls;
+
+/// How long to wait for the connect and then for each handshake message.
+pub const TLS_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Maximum number of ports inspected at once.
+pub const MAX_TLS_WORKERS: usize = 32;
+
+/// Application protocols offered through ALPN, most preferred first.
+const ALPN_PROTOCOLS: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
+/// The client configuration shared by every handshake.
+static CLIENT_CONFIG: Lazy<Arc<ClientConfig>> = Lazy::new(|| {
+    let provider = Arc::new(crypto::ring::default_provider());
+    let mut config = ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .expect("The ring provider supports the default protocol versions")
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+        .with_no_client_auth();
+    config.alpn_protocols = ALPN_PROTOCOLS.iter().map(|p| p.to_vec()).collect();
+    Arc::new(config)
+});
+
+/// Accepts any certificate, while still checking the handshake signatures
+/// made with it.
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Completes a TLS handshake with a TCP port and returns what it revealed.
+///
+/// `hostname` is sent as the server name, so that servers hosting several
+/// names present the right certificate. If an `upgrade` is given, the
+/// service is first asked to switch the connection to TLS.
+pub fn inspect(
+    addr: &SocketAddr,
+    hostname: Option<&str>,
+    upgrade: Option<StartTls>,
+    timeout: Duration,
+) -> io::Result<TlsInfo> {
+    let server_name = hostname
+        .and_then(|hostname| ServerName::try_from(hostname.to_string()).ok())
+        .unwrap_or_else(|| ServerName::IpAddress(addr.ip().into()));
+    let mut connection =
+        ClientConnection::new(Arc::clone(&CLIENT_CONFIG), server_name).map_err(io::Error::other)?;
+
+    let mut stream = TcpStream::connect_timeout(addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    if let Some(upgrade) = upgrade {
+        let domain = hostname.map_or_else(|| addr.ip().to_string(), str::to_string);
+        upgrade.negotiate(&mut stream, &domain)?;
+    }
+    while connection.is_handshaking() {
+        connection.complete_io(&mut stream)?;
+    }
+    connection.send_close_notify();
+    let _ = connection.complete_io(&mut stream);
+
+    let version = match connection.protocol_version() {
+        Some(ProtocolVersion::TLSv1_2) => "TLSv1.2".to_string(),
+        Some(ProtocolVersion::TLSv1_3) => "TLSv1.3".to_string(),
+        Some(version) => format!("{:?}", version),
+        None => String::new(),
+    };
+
+    Ok(TlsInfo {
+        version,
+        upgrade: upgrade.map(|upgrade| upgrade.command()),
+        cipher_suite: connection
+            .negotiated_cipher_suite()
+            .map(|suite| format!("{:?}", suite.suite()))
+            .unwrap_or_default(),
+        alpn: connection
+            .alpn_protocol()
+            .map(|protocol| String::from_utf8_lossy(protocol).into_owned()),
+        certificate: connection
+            .peer_certificates()
+            .and_then(|chain| chain.first())
+            .and_then(|der| parse_certificate(der)),
+    })
+}
+
+/// Reads the details of a DER encoded certificate.
+///
+/// Names are sanitized like banners, so that control characters in a hostile
+/// certificate cannot reach a terminal or a report. Returns `None` if the
+/// certificate cannot be parsed.
+pub fn parse_certificate(der: &[u8]) -> Option<CertificateInfo> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+
+    let mut sans = Vec::new();
+    if let Ok(Some(extension)) = cert.subject_alternative_name() {
+        for name in &extension.value.general_names {
+            match name {
+                GeneralName::DNSName(name) => sans.push(banner::sanitize(name.as_bytes())),
+                GeneralName::IPAddress(bytes) => {
+                    let ip = match bytes.len() {
+                        4 => <[u8; 4]>::try_from(*bytes).ok().map(IpAddr::from),
+                        16 => <[u8; 16]>::try_from(*bytes).ok().map(IpAddr::from),
+                        _ => None,
+                    };
+                    sans.extend(ip.map(|ip| ip.to_string()));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let fingerprint = Sha256::digest(der)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":");
+
+    Some(CertificateInfo {
+        subject: banner::sanitize(cert.subject().to_string().as_bytes()),
+        issuer: banner::sanitize(cert.issuer().to_string().as_bytes()),
+        sans,
+        not_before: system_time(cert.validity().not_before.timestamp()),
+        not_after: system_time(cert.validity().not_after.timestamp()),
+        sha256: fingerprint,
+    })
+}
+
+/// Converts seconds since the Unix epoch, which may be negative, to a time.
+fn system_time(timestamp: i64) -> SystemTime {
+    let offset = Duration::from_secs(timestamp.unsigned_abs());
+    match timestamp < 0 {
+        true => UNIX_EPOCH - offset,
+        false => UNIX_EPOCH + offset,
+    }
+}
+
+/// Inspects the TLS service of every open TCP port in `results` and stores
+/// what the handshakes revealed on the results.
+///
+/// Ports whose registered service starts in plaintext are upgraded first, see
+/// [`StartTls::for_port`].
+///
+/// Up to `max_parallelism` (at most [`MAX_TLS_WORKERS`]) ports are inspected
+/// at once. No new ports are inspected once the scan is cancelled.
+pub fn inspect_ports(
+    addr: &IpAddr,
+    hostname: Option<&str>,
+    results: &mut [ScanResult],
+    options: &ScanOptions,
+) {
+    let ports: Vec<_> = results
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| r.protocol == ScanProtocol::Tcp && r.state == PortState::Open)
+        .map(|(i, r)| (i, r.port))
+        .collect();
+    let next = AtomicUsize::new(0);
+    let inspected = Mutex::new(Vec::new());
+    let n_workers = options
+        .max_parallelism
+        .min(MAX_TLS_WORKERS)
+        .min(ports.len());
+
+    thread::scope(|scope| {
+        for _ in 0..n_workers {
+            scope.spawn(|| loop {
+                if options.is_cancelled() {
+                    return;
+                }
+                let Some(&(i, port)) = ports.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                    return;
+                };
+                let upgrade = StartTls::for_port(port);
+                let addr = SocketAddr::new(*addr, port);
+                if let Ok(info) = inspect(&addr, hostname, upgrade, TLS_TIMEOUT) {
+                    inspected.lock().unwrap().push((i, info));
+                }
+            });
+        }
+    });
+
+    for (i, info) in inspected.into_inner().unwrap() {
+        results[i].tls = Some(info);
+    }
+}
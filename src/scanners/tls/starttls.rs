@@ -0,0 +1,218 @@
+//! Plaintext exchanges that upgrade a connection to TLS.
+//!
+//! Mail, file transfer, chat and database services often listen in plaintext
+//! and only switch to TLS when the client asks. [`StartTls`] holds the request
+//! of each supported protocol. The upgrade for a port is picked from the IANA
+//! service name registered for it, see [`StartTls::for_port`].
+use crate::results::service_name;
+use crate::scanners::ScanProtocol;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+/// Maximum number of bytes read from a single plaintext response.
+const MAX_RESPONSE_LEN: usize = 8192;
+
+/// Maximum number of lines read while waiting for the end of a response.
+const MAX_RESPONSE_LINES: usize = 64;
+
+/// The PostgreSQL `SSLRequest` message: its length, then the request code 80877103.
+const POSTGRES_SSL_REQUEST: [u8; 8] = [0x00, 0x00, 0x00, 0x08, 0x04, 0xd2, 0x16, 0x2f];
+
+/// A protocol specific request to switch a plaintext connection to TLS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartTls {
+    /// SMTP `STARTTLS` after `EHLO` (RFC 3207)
+    Smtp,
+    /// IMAP `STARTTLS` (RFC 3501)
+    Imap,
+    /// POP3 `STLS` (RFC 2595)
+    Pop3,
+    /// FTP `AUTH TLS` (RFC 4217)
+    Ftp,
+    /// XMPP `<starttls/>` on a client to server stream (RFC 6120)
+    XmppClient,
+    /// XMPP `<starttls/>` on a server to server stream (RFC 6120)
+    XmppServer,
+    /// PostgreSQL `SSLRequest`
+    Postgres,
+}
+
+impl StartTls {
+    /// Returns the upgrade for an IANA service name, e.g. `smtp` or `submission`.
+    pub fn for_service(name: &str) -> Option<StartTls> {
+        match name {
+            "smtp" | "submission" => Some(StartTls::Smtp),
+            "imap" => Some(StartTls::Imap),
+            "pop3" => Some(StartTls::Pop3),
+            "ftp" => Some(StartTls::Ftp),
+            "xmpp-client" => Some(StartTls::XmppClient),
+            "xmpp-server" => Some(StartTls::XmppServer),
+            "postgresql" => Some(StartTls::Postgres),
+            _ => None,
+        }
+    }
+
+    /// Returns the upgrade for the service registered for a TCP port.
+    pub fn for_port(port: u16) -> Option<StartTls> {
+        service_name(&ScanProtocol::Tcp, port).and_then(StartTls::for_service)
+    }
+
+    /// Returns the name of the request, e.g. `STARTTLS` or `AUTH TLS`.
+    pub fn command(&self) -> &'static str {
+        match self {
+            StartTls::Smtp | StartTls::Imap | StartTls::XmppClient | StartTls::XmppServer => {
+                "STARTTLS"
+            }
+            StartTls::Pop3 => "STLS",
+            StartTls::Ftp => "AUTH TLS",
+            StartTls::Postgres => "SSLRequest",
+        }
+    }
+
+    /// Asks the service on a fresh connection to switch to TLS.
+    ///
+    /// On success the next bytes on the connection belong to the TLS
+    /// handshake. `domain` is the name XMPP streams are addressed to. Fails
+    /// with [`io::ErrorKind::Unsupported`] if the service refuses.
+    pub fn negotiate(&self, stream: &mut TcpStream, domain: &str) -> io::Result<()> {
+        match self {
+            StartTls::Smtp => {
+                expect(&read_reply(stream)?, "220", self)?;
+                stream.write_all(b"EHLO pscan\r\n")?;
+                expect(&read_reply(stream)?, "250", self)?;
+                stream.write_all(b"STARTTLS\r\n")?;
+                expect(&read_reply(stream)?, "220", self)
+            }
+            StartTls::Imap => {
+                expect(&read_line(stream)?, "* OK", self)?;
+                stream.write_all(b"a1 STARTTLS\r\n")?;
+                // Untagged responses may come before the tagged one.
+                for _ in 0..MAX_RESPONSE_LINES {
+                    let line = read_line(stream)?;
+                    if line.starts_with("a1 ") {
+                        return expect(&line, "a1 OK", self);
+                    }
+                }
+                Err(too_long())
+            }
+            StartTls::Pop3 => {
+                expect(&read_line(stream)?, "+OK", self)?;
+                stream.write_all(b"STLS\r\n")?;
+                expect(&read_line(stream)?, "+OK", self)
+            }
+            StartTls::Ftp => {
+                expect(&read_reply(stream)?, "220", self)?;
+                stream.write_all(b"AUTH TLS\r\n")?;
+                expect(&read_reply(stream)?, "234", self)
+            }
+            StartTls::XmppClient | StartTls::XmppServer => {
+                let namespace = match self {
+                    StartTls::XmppServer => "jabber:server",
+                    _ => "jabber:client",
+                };
+                write!(
+                    stream,
+                    "<?xml version='1.0'?><stream:stream to='{}' xmlns='{}' \
+                     xmlns:stream='http://etherx.jabber.org/streams' version='1.0'>",
+                    domain, namespace
+                )?;
+                let features = read_until(stream, "</stream:features>")?;
+                if !features.contains("urn:ietf:params:xml:ns:xmpp-tls") {
+                    return Err(refused(self, "no starttls feature"));
+                }
+                stream.write_all(b"<starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>")?;
+                let answer = read_until(stream, ">")?;
+                match answer.contains("<proceed") {
+                    true => Ok(()),
+                    false => Err(refused(self, &answer)),
+                }
+            }
+            StartTls::Postgres => {
+                stream.write_all(&POSTGRES_SSL_REQUEST)?;
+                let mut answer = [0u8; 1];
+                stream.read_exact(&mut answer)?;
+                match answer[0] {
+                    b'S' => Ok(()),
+                    _ => Err(refused(self, "server does not accept TLS")),
+                }
+            }
+        }
+    }
+}
+
+/// Reads one line of a plaintext response, without its line break.
+fn read_line(stream: &mut TcpStream) -> io::Result<String> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    // Bytes are read one at a time, so that nothing after the line is consumed.
+    while line.len() < MAX_RESPONSE_LEN {
+        match stream.read(&mut byte)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            _ if byte[0] == b'\n' => break,
+            _ => line.push(byte[0]),
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+}
+
+/// Reads a reply of a protocol with numbered replies such as SMTP and FTP and
+/// returns its last line.
+///
+/// A reply continues over several lines while its first line has a `-` after
+/// the code, and ends with a line starting with the code and a space. Fails
+/// if it has not ended after [`MAX_RESPONSE_LINES`] lines.
+fn read_reply(stream: &mut TcpStream) -> io::Result<String> {
+    let first = read_line(stream)?;
+    let Some(code) = first.get(..3).filter(|_| first[3..].starts_with('-')) else {
+        return Ok(first);
+    };
+
+    let last = format!("{} ", code);
+    for _ in 1..MAX_RESPONSE_LINES {
+        let line = read_line(stream)?;
+        if line.starts_with(&last) {
+            return Ok(line);
+        }
+    }
+
+    Err(too_long())
+}
+
+/// Reads a response until it ends with `end`.
+fn read_until(stream: &mut TcpStream, end: &str) -> io::Result<String> {
+    let mut response = Vec::new();
+    let mut byte = [0u8; 1];
+    while !response.ends_with(end.as_bytes()) {
+        if response.len() >= MAX_RESPONSE_LEN {
+            return Err(too_long());
+        }
+        match stream.read(&mut byte)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            _ => response.push(byte[0]),
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&response).into_owned())
+}
+
+/// The error of a response that does not end within the limits.
+fn too_long() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Response is too long")
+}
+
+/// Fails unless a response starts with the code or text of an agreeing answer.
+fn expect(response: &str, prefix: &str, upgrade: &StartTls) -> io::Result<()> {
+    match response.starts_with(prefix) {
+        true => Ok(()),
+        false => Err(refused(upgrade, response)),
+    }
+}
+
+/// The error of a service that did not agree to switch to TLS.
+fn refused(upgrade: &StartTls, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{} refused: {}", upgrade.command(), reason),
+    )
+}
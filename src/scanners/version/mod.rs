@@ -0,0 +1,589 @@
+//! Service and version detection.
+//!
+//! The SERVICE column of a plain scan is only the IANA registered name of the
+//! port number, so an HTTP server on port 2222 shows up as whatever is
+//! registered for 2222. Version detection asks the service itself instead: it
+//! sends protocol probes to each open port and matches the responses against a
+//! database of regular expressions, in the spirit of nmap's
+//! `nmap-service-probes`.
+//!
+//! A [`ServiceDatabase`] holds [`ServiceProbe`]s, each with the payload it
+//! sends and the [`ServiceMatch`]es its responses are compared with. A match
+//! names the service and may fill in the product, version and extra info from
+//! the groups its pattern captured, using `$1` to `$9` in its templates.
+//!
+//! For every open port the probes are tried in this order until one gets a
+//! full match:
+//!
+//! 1. The NULL probe, which sends nothing and waits for a greeting (TCP only)
+//! 2. The probes registered for the port
+//! 3. Every other probe of the port's protocol that is not rarer than the
+//!    version intensity
+//!
+//! A response is matched against the patterns of its probe, then those of the
+//! probes it falls back to and, for TCP, those of the NULL probe. Soft matches
+//! only name the service. After one, only the probes with full matches for
+//! that service are still tried, and if none of them finds one the service is
+//! reported with a lower confidence.
+//!
+//! Besides the built-in database, databases can be read from files in nmap's
+//! `nmap-service-probes` format, see [`parser`].
+//!
+//! # Example
+//! ```no_run
+//! use pscan::scanners::version::{self, ServiceDatabase};
+//! use pscan::scanners::ScanProtocol;
+//!
+//! let addr = "127.0.0.1:22".parse().unwrap();
+//! let database = ServiceDatabase::builtin();
+//! let intensity = version::DEFAULT_VERSION_INTENSITY;
+//! if let Some(service) = version::detect(&addr, &ScanProtocol::Tcp, &database, intensity) {
+//!     println!("{} {}", service.name, service.version_line().unwrap_or_default());
+//! }
+//! ```
+use crate::results::{PortState, ScanResult, ServiceInfo};
+use crate::scanners::{banner, PortSet, ScanOptions, ScanProtocol};
+use once_cell::sync::Lazy;
+use regex::bytes::{Captures, Regex, RegexBuilder};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub mod parser;
+mod probes;
+
+/// Confidence of a service identified by a full match.
+pub const MATCH_CONFIDENCE: u8 = 10;
+
+/// Confidence of a service only named by a soft match.
+pub const SOFT_MATCH_CONFIDENCE: u8 = 7;
+
+/// Maximum number of ports probed at once by [`detect_services`].
+pub const MAX_VERSION_WORKERS: usize = 32;
+
+/// Maximum number of response bytes matched against a probe's patterns.
+const MAX_RESPONSE_LEN: usize = 8192;
+
+/// Rarity of the probes up to which every probe is sent, like nmap's default
+/// `--version-intensity`.
+pub const DEFAULT_VERSION_INTENSITY: u8 = 7;
+
+/// How long a probe waits for a response unless it sets its own wait.
+pub const DEFAULT_PROBE_WAIT: Duration = Duration::from_secs(2);
+
+/// The built-in database, compiled on first use.
+static BUILTIN: Lazy<Arc<ServiceDatabase>> = Lazy::new(|| Arc::new(probes::builtin()));
+
+/// A pattern a probe's response is compared with, and what a match reveals.
+#[derive(Debug, Clone)]
+pub struct ServiceMatch {
+    /// The service named by a match
+    pub service: String,
+    /// The pattern matched against the raw response bytes
+    pub pattern: Regex,
+    /// True if a match only names the service and other probes should still be tried
+    pub soft: bool,
+    /// Template of the product name
+    pub product: Option<String>,
+    /// Template of the product version
+    pub version: Option<String>,
+    /// Template of the extra info
+    pub extra_info: Option<String>,
+    /// Template of the hostname
+    pub hostname: Option<String>,
+    /// Template of the operating system
+    pub os: Option<String>,
+    /// Template of the device type
+    pub device_type: Option<String>,
+    /// Templates of the CPE names
+    pub cpe: Vec<String>,
+}
+
+impl ServiceMatch {
+    /// Creates a full match for `service`.
+    ///
+    /// The pattern is matched against bytes rather than text, so escapes such
+    /// as `\xff` match single bytes. Inline flags like `(?s)` and `(?i)` can
+    /// be used to change how it matches.
+    pub fn new(service: &str, pattern: &str) -> Result<Self, String> {
+        let pattern = RegexBuilder::new(pattern)
+            .unicode(false)
+            .build()
+            .map_err(|e| {
+                // Syntax errors draw the pattern over several lines, the reason is on the last one.
+                let e = e.to_string();
+                let reason = e.lines().last().unwrap_or_default();
+                let reason = reason.trim_start_matches("error: ");
+                format!("Invalid pattern for {}: {}", service, reason)
+            })?;
+
+        Ok(ServiceMatch {
+            service: service.to_string(),
+            pattern,
+            soft: false,
+            product: None,
+            version: None,
+            extra_info: None,
+            hostname: None,
+            os: None,
+            device_type: None,
+            cpe: Vec::new(),
+        })
+    }
+
+    /// Creates a soft match for `service`, see [`ServiceMatch::new`].
+    pub fn soft(service: &str, pattern: &str) -> Result<Self, String> {
+        ServiceMatch::new(service, pattern).map(|m| ServiceMatch { soft: true, ..m })
+    }
+
+    /// Sets the template of the product name.
+    pub fn with_product(mut self, template: &str) -> Self {
+        self.product = Some(template.to_string());
+        self
+    }
+
+    /// Sets the template of the product version.
+    pub fn with_version(mut self, template: &str) -> Self {
+        self.version = Some(template.to_string());
+        self
+    }
+
+    /// Sets the template of the extra info.
+    pub fn with_extra_info(mut self, template: &str) -> Self {
+        self.extra_info = Some(template.to_string());
+        self
+    }
+
+    /// Matches a response and returns the service it reveals.
+    pub fn apply(&self, response: &[u8]) -> Option<ServiceInfo> {
+        let captures = self.pattern.captures(response)?;
+        let fill = |template: &Option<String>| {
+            template
+                .as_deref()
+                .and_then(|template| fill_template(template, &captures))
+        };
+
+        Some(ServiceInfo {
+            name: self.service.clone(),
+            product: fill(&self.product),
+            version: fill(&self.version),
+            extra_info: fill(&self.extra_info),
+            hostname: fill(&self.hostname),
+            os: fill(&self.os),
+            device_type: fill(&self.device_type),
+            cpe: self
+                .cpe
+                .iter()
+                .filter_map(|template| fill_template(template, &captures))
+                .collect(),
+            confidence: match self.soft {
+                true => SOFT_MATCH_CONFIDENCE,
+                false => MATCH_CONFIDENCE,
+            },
+        })
+    }
+}
+
+/// Matches the references to captured groups in a template.
+static TEMPLATE_REFERENCE: Lazy<regex::Regex> = Lazy::new(|| {
+    regex::Regex::new(
+        r#"\$(?:(\d)|P\((\d)\)|SUBST\((\d),"([^"]*)","([^"]*)"\)|I\((\d),"([<>])"\))"#,
+    )
+    .unwrap()
+});
+
+/// Fills a template with the groups captured by a match.
+///
+/// `$1` to `$9` are replaced with the sanitized groups. The helpers of nmap's
+/// templates work too: `$P(1)` keeps only the printable characters of a group,
+/// `$SUBST(1,"_",".")` replaces text within it and `$I(1,">")` reads it as a
+/// big (`>`) or little (`<`) endian unsigned integer.
+///
+/// Returns `None` if nothing but whitespace is left.
+fn fill_template(template: &str, captures: &Captures) -> Option<String> {
+    let filled = TEMPLATE_REFERENCE.replace_all(template, |reference: &regex::Captures| {
+        let group = |i: usize| {
+            let n: usize = reference[i].parse().unwrap();
+            captures.get(n).map_or(&[][..], |value| value.as_bytes())
+        };
+
+        if reference.get(1).is_some() {
+            banner::sanitize(group(1))
+        } else if reference.get(2).is_some() {
+            group(2)
+                .iter()
+                .filter(|byte| (b' '..=b'~').contains(*byte))
+                .map(|&byte| byte as char)
+                .collect()
+        } else if reference.get(3).is_some() {
+            let value = banner::sanitize(group(3));
+            match &reference[4] {
+                "" => value,
+                from => value.replace(from, &reference[5]),
+            }
+        } else {
+            let bytes = group(6);
+            let bytes = &bytes[..bytes.len().min(8)];
+            let value = match &reference[7] {
+                ">" => bytes.iter().fold(0u64, |acc, &b| acc << 8 | u64::from(b)),
+                _ => bytes
+                    .iter()
+                    .rev()
+                    .fold(0u64, |acc, &b| acc << 8 | u64::from(b)),
+            };
+            value.to_string()
+        }
+    });
+
+    let filled = filled.trim();
+    (!filled.is_empty()).then(|| filled.to_string())
+}
+
+/// A request sent to a port, and the patterns its responses are matched against.
+#[derive(Debug, Clone)]
+pub struct ServiceProbe {
+    /// Name of the probe, e.g. `NULL` or `GetRequest`
+    pub name: String,
+    /// Protocol the probe is sent over
+    pub protocol: ScanProtocol,
+    /// The bytes sent once connected, empty to only listen for a greeting
+    pub payload: Vec<u8>,
+    /// Ports the probe is tried on first
+    pub ports: Vec<u16>,
+    /// Ports where nmap sends the probe inside a TLS session. pscan always
+    /// sends probes in the clear, so these are only kept for reference.
+    pub ssl_ports: Vec<u16>,
+    /// How seldom the probe gets a response, from 1 (often) to 9 (rarely)
+    pub rarity: u8,
+    /// Names of the probes whose patterns are also tried on the response
+    pub fallback: Vec<String>,
+    /// How long to wait for the response
+    pub wait: Duration,
+    /// Patterns the response is matched against, in order
+    pub matches: Vec<ServiceMatch>,
+}
+
+impl ServiceProbe {
+    /// Returns true if the probe sends nothing and only waits for a greeting.
+    pub fn is_null(&self) -> bool {
+        self.payload.is_empty() && self.protocol == ScanProtocol::Tcp
+    }
+
+    /// Returns the first full match of a response, or else its first soft match.
+    ///
+    /// The probe's own patterns are tried before those of its `fallbacks`.
+    pub fn match_response(
+        &self,
+        response: &[u8],
+        fallbacks: &[&ServiceProbe],
+    ) -> Option<ServiceInfo> {
+        let mut soft = None;
+        let fallback_matches = fallbacks.iter().flat_map(|probe| &probe.matches);
+        for service_match in self.matches.iter().chain(fallback_matches) {
+            match service_match.apply(response) {
+                Some(info) if !service_match.soft => return Some(info),
+                Some(info) => {
+                    soft.get_or_insert(info);
+                }
+                None => {}
+            }
+        }
+
+        soft
+    }
+
+    /// Sends the probe and matches the response, see [`ServiceProbe::match_response`].
+    ///
+    /// Stops reading as soon as the response so far is a full match, otherwise
+    /// reads until the probe's wait is over or the service closes the connection.
+    pub fn run(
+        &self,
+        addr: &SocketAddr,
+        fallbacks: &[&ServiceProbe],
+    ) -> io::Result<Option<ServiceInfo>> {
+        let deadline = Instant::now() + self.wait;
+        let mut response = Vec::new();
+        let mut buffer = [0u8; 2048];
+        let mut connection = match self.protocol {
+            ScanProtocol::Tcp => {
+                let mut stream = TcpStream::connect_timeout(addr, self.wait)?;
+                if !self.payload.is_empty() {
+                    stream.write_all(&self.payload)?;
+                }
+                Connection::Tcp(stream)
+            }
+            ScanProtocol::Udp => {
+                let socket = match addr {
+                    SocketAddr::V4(_) => UdpSocket::bind("0.0.0.0:0"),
+                    SocketAddr::V6(_) => UdpSocket::bind("[::]:0"),
+                }?;
+                socket.connect(addr)?;
+                socket.send(&self.payload)?;
+                Connection::Udp(socket)
+            }
+        };
+
+        while response.len() < MAX_RESPONSE_LEN {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            match connection.read(&mut buffer, remaining) {
+                Ok(0) => break,
+                Ok(n) => response.extend_from_slice(&buffer[..n]),
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock
+                            | io::ErrorKind::TimedOut
+                            | io::ErrorKind::Interrupted
+                    ) =>
+                {
+                    break
+                }
+                // A reset after the response still leaves the response to match.
+                Err(_) if !response.is_empty() => break,
+                Err(e) => return Err(e),
+            }
+            if let Some(info) = self.match_response(&response, fallbacks) {
+                if info.confidence == MATCH_CONFIDENCE {
+                    return Ok(Some(info));
+                }
+            }
+        }
+
+        Ok(self.match_response(&response, fallbacks))
+    }
+}
+
+/// The socket a probe was sent over.
+enum Connection {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+impl Connection {
+    /// Reads the next part of the response, waiting at most `wait` for it.
+    fn read(&mut self, buffer: &mut [u8], wait: Duration) -> io::Result<usize> {
+        match self {
+            Connection::Tcp(stream) => {
+                stream.set_read_timeout(Some(wait))?;
+                stream.read(buffer)
+            }
+            Connection::Udp(socket) => {
+                socket.set_read_timeout(Some(wait))?;
+                socket.recv(buffer)
+            }
+        }
+    }
+}
+
+/// A set of probes and the patterns their responses are matched against.
+#[derive(Debug, Clone, Default)]
+pub struct ServiceDatabase {
+    pub probes: Vec<ServiceProbe>,
+    /// Ports that are never probed, as given by an `Exclude` directive
+    pub excluded: Option<PortSet>,
+}
+
+impl ServiceDatabase {
+    /// Returns the database built into pscan, covering common services such
+    /// as SSH, FTP, SMTP, POP3, IMAP, HTTP, MySQL and TLS.
+    pub fn builtin() -> Arc<ServiceDatabase> {
+        Arc::clone(&BUILTIN)
+    }
+
+    /// Parses a database in the `nmap-service-probes` format, see [`parser`].
+    ///
+    /// Lines that cannot be used do not abort the parse, they are skipped and
+    /// returned as errors tagged with `source` and their line number instead.
+    pub fn parse(text: &str, source: &str) -> (ServiceDatabase, Vec<String>) {
+        parser::parse(text, source)
+    }
+
+    /// Reads a database in the `nmap-service-probes` format from a file, see
+    /// [`ServiceDatabase::parse`].
+    ///
+    /// Lines that are not valid UTF-8 are skipped and returned as errors, unless
+    /// they are comments.
+    pub fn read_file(path: &str) -> io::Result<(ServiceDatabase, Vec<String>)> {
+        Ok(parser::parse_bytes(&fs::read(path)?, path))
+    }
+
+    /// Adds the probes of another database.
+    ///
+    /// The patterns of a probe this database already has, identified by its
+    /// protocol and name, are tried before the existing ones, and its ports
+    /// and fallbacks are added to the existing probe's. Other probes are
+    /// appended, and the excluded ports of both databases are combined.
+    pub fn merge(&mut self, other: ServiceDatabase) {
+        for probe in other.probes {
+            match self
+                .probes
+                .iter_mut()
+                .find(|p| p.protocol == probe.protocol && p.name == probe.name)
+            {
+                Some(existing) => {
+                    existing.matches.splice(0..0, probe.matches);
+                    existing.ports.extend(probe.ports);
+                    existing.ssl_ports.extend(probe.ssl_ports);
+                    existing.fallback.extend(probe.fallback);
+                }
+                None => self.probes.push(probe),
+            }
+        }
+
+        self.excluded = match (self.excluded.take(), other.excluded) {
+            (Some(mut excluded), Some(other)) => {
+                excluded.extend(&other);
+                Some(excluded)
+            }
+            (excluded, other) => excluded.or(other),
+        };
+    }
+
+    /// Returns true if a port must not be probed.
+    pub fn is_excluded(&self, protocol: &ScanProtocol, port: u16) -> bool {
+        self.excluded
+            .as_ref()
+            .is_some_and(|excluded| excluded.ports(protocol).binary_search(&port).is_ok())
+    }
+
+    /// Returns the probes to try on a port, in the order they are tried.
+    ///
+    /// Probes rarer than `intensity` (0 to 9) are left out, unless they are
+    /// the NULL probe or registered for the port.
+    pub fn probes_for(
+        &self,
+        protocol: &ScanProtocol,
+        port: u16,
+        intensity: u8,
+    ) -> Vec<&ServiceProbe> {
+        let mut probes: Vec<_> = self
+            .probes
+            .iter()
+            .filter(|probe| probe.protocol == *protocol)
+            .filter(|probe| {
+                probe.is_null() || probe.rarity <= intensity || probe.ports.contains(&port)
+            })
+            .collect();
+        probes.sort_by_key(|probe| (!probe.is_null(), !probe.ports.contains(&port)));
+
+        probes
+    }
+
+    /// Returns the probes whose patterns are tried after a probe's own.
+    ///
+    /// These are the probes named by its `fallback` directives, followed by
+    /// the NULL probe for TCP probes since a service may send its greeting
+    /// whatever it was sent.
+    pub fn fallbacks(&self, probe: &ServiceProbe) -> Vec<&ServiceProbe> {
+        let mut fallbacks: Vec<&ServiceProbe> = probe
+            .fallback
+            .iter()
+            .filter_map(|name| {
+                self.probes
+                    .iter()
+                    .find(|p| p.protocol == probe.protocol && p.name == *name)
+            })
+            .collect();
+        if probe.protocol == ScanProtocol::Tcp && !probe.is_null() {
+            fallbacks.extend(self.probes.iter().filter(|p| p.is_null()));
+        }
+        fallbacks.retain(|p| !std::ptr::eq(*p, probe));
+
+        fallbacks
+    }
+}
+
+/// Identifies the service on an open port.
+///
+/// Only the probes not rarer than `intensity` are sent, see
+/// [`ServiceDatabase::probes_for`]. Returns the first full match, or else the
+/// first soft match, or `None` if no probe got a response that matched.
+pub fn detect(
+    addr: &SocketAddr,
+    protocol: &ScanProtocol,
+    database: &ServiceDatabase,
+    intensity: u8,
+) -> Option<ServiceInfo> {
+    let mut soft: Option<ServiceInfo> = None;
+    for probe in database.probes_for(protocol, addr.port(), intensity) {
+        let fallbacks = database.fallbacks(probe);
+        // After a soft match, only probes that can tell more about that service are worth sending.
+        if let Some(ref soft) = soft {
+            let mut matches = probe
+                .matches
+                .iter()
+                .chain(fallbacks.iter().flat_map(|p| &p.matches));
+            if !matches.any(|m| m.service == soft.name && !m.soft) {
+                continue;
+            }
+        }
+        match probe.run(addr, &fallbacks) {
+            Ok(Some(info)) if info.confidence == MATCH_CONFIDENCE => return Some(info),
+            Ok(Some(info)) => {
+                soft.get_or_insert(info);
+            }
+            Ok(None) => {}
+            // The port no longer accepts connections, no other probe will get through.
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => break,
+            Err(_) => {}
+        }
+    }
+
+    soft
+}
+
+/// Runs [`detect`] on every open port in `results` and stores the services
+/// found on the results. Ports excluded by the database are skipped.
+///
+/// Up to `max_parallelism` (at most [`MAX_VERSION_WORKERS`]) ports are probed
+/// at once. No new ports are probed once the scan is cancelled.
+pub fn detect_services(
+    addr: &IpAddr,
+    results: &mut [ScanResult],
+    options: &ScanOptions,
+    database: &ServiceDatabase,
+    intensity: u8,
+) {
+    let ports: Vec<_> = results
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| r.state == PortState::Open)
+        .filter(|(_, r)| !database.is_excluded(&r.protocol, r.port))
+        .map(|(i, r)| (i, r.protocol.clone(), r.port))
+        .collect();
+    let next = AtomicUsize::new(0);
+    let services = Mutex::new(Vec::new());
+    let n_workers = options
+        .max_parallelism
+        .min(MAX_VERSION_WORKERS)
+        .min(ports.len());
+
+    thread::scope(|scope| {
+        for _ in 0..n_workers {
+            scope.spawn(|| loop {
+                if options.is_cancelled() {
+                    return;
+                }
+                let Some((i, protocol, port)) = ports.get(next.fetch_add(1, Ordering::Relaxed))
+                else {
+                    return;
+                };
+                let addr = SocketAddr::new(*addr, *port);
+                if let Some(service) = detect(&addr, protocol, database, intensity) {
+                    services.lock().unwrap().push((*i, service));
+                }
+            });
+        }
+    });
+
+    for (i, service) in services.into_inner().unwrap() {
+        results[i].service = Some(service);
+    }
+}
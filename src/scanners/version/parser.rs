@@ -0,0 +1,478 @@
+//! Parser for nmap's `nmap-service-probes` format.
+//!
+//! Probe databases written for nmap can be used as they are. The format is
+//! line based and lines starting with `#` are comments. A `Probe` line starts
+//! a probe and the directives after it, up to the next probe, describe it:
+//!
+//! ```text
+//! Exclude T:9100-9107
+//!
+//! Probe TCP GetRequest q|GET / HTTP/1.0\r\n\r\n|
+//! rarity 1
+//! ports 80,8000-8010
+//! sslports 443
+//! totalwaitms 5000
+//! fallback GenericLines
+//! match http m|^HTTP/1\.[01] \d\d\d .*\r\nServer: nginx/([\d.]+)|s p/nginx/ v/$1/ cpe:/a:nginx:nginx:$1/
+//! softmatch http m|^HTTP/1\.[01] \d\d\d|
+//! ```
+//!
+//! The supported directives are `Exclude`, `Probe`, `match`, `softmatch`,
+//! `ports`, `sslports`, `rarity`, `totalwaitms` and `fallback`.
+//! `tcpwrappedms` is accepted but has no effect. Probes without a `rarity`
+//! get a rarity of 5, and fallbacks naming unknown probes are ignored.
+//!
+//! A match can fill in the product (`p/`), version (`v/`), extra info (`i/`),
+//! hostname (`h/`), operating system (`o/`) and device type (`d/`) of the
+//! service, and any number of CPE names (`cpe:/`). Any character may delimit
+//! these fields, as well as the probe string and the pattern.
+//!
+//! nmap patterns are PCRE, while pscan uses the `regex` crate. Octal escapes
+//! such as `\0` and the `\Z` anchor are rewritten, but patterns relying on
+//! look-around or backreferences cannot be compiled and their lines are
+//! skipped, as are lines that are not valid UTF-8 outside of comments.
+//!
+//! # Example
+//! ```no_run
+//! use pscan::scanners::version::ServiceDatabase;
+//!
+//! let (database, errors) = ServiceDatabase::read_file("nmap-service-probes").unwrap();
+//! for error in errors {
+//!     eprintln!("Warning: {}", error);
+//! }
+//! println!("{} probes", database.probes.len());
+//! ```
+use super::{ServiceDatabase, ServiceMatch, ServiceProbe, DEFAULT_PROBE_WAIT};
+use crate::scanners::{PortRange, PortSet, ScanProtocol};
+use std::fmt::Write as _;
+use std::time::Duration;
+
+/// Rarity of a probe without a `rarity` directive.
+pub const DEFAULT_RARITY: u8 = 5;
+
+/// Parses a probe database, see [`ServiceDatabase::parse`].
+pub fn parse(text: &str, source: &str) -> (ServiceDatabase, Vec<String>) {
+    parse_lines(text.lines().map(Ok), source)
+}
+
+/// Parses a probe database that may not be valid UTF-8, see
+/// [`ServiceDatabase::read_file`].
+pub fn parse_bytes(bytes: &[u8], source: &str) -> (ServiceDatabase, Vec<String>) {
+    let lines = bytes
+        .split(|&byte| byte == b'\n')
+        .map(|line| std::str::from_utf8(line).map_err(|_| line));
+    parse_lines(lines, source)
+}
+
+/// Parses the lines of a probe database, given as text or as the bytes of a
+/// line that is not valid UTF-8.
+fn parse_lines<'a>(
+    lines: impl Iterator<Item = Result<&'a str, &'a [u8]>>,
+    source: &str,
+) -> (ServiceDatabase, Vec<String>) {
+    let mut database = ServiceDatabase::default();
+    let mut errors = Vec::new();
+    let mut probe: Option<ServiceProbe> = None;
+    // Set after an invalid Probe line, so that its directives are not added to the previous probe.
+    let mut skipping = false;
+
+    for (i, line) in lines.enumerate() {
+        let line = match line {
+            Ok(line) => line.trim(),
+            // Comments are skipped whatever they contain.
+            Err(bytes) if bytes.trim_ascii_start().starts_with(b"#") => continue,
+            Err(bytes) => {
+                if bytes.trim_ascii_start().starts_with(b"Probe") {
+                    skipping = true;
+                }
+                errors.push(format!("{}:{}: Line is not valid UTF-8", source, i + 1));
+                continue;
+            }
+        };
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (directive, spec) = next_word(line);
+        let result = match directive {
+            "Exclude" => spec
+                .parse::<PortSet>()
+                .map(|ports| match database.excluded {
+                    Some(ref mut excluded) => excluded.extend(&ports),
+                    None => database.excluded = Some(ports),
+                }),
+            "Probe" => {
+                database.probes.extend(probe.take());
+                let parsed = parse_probe(spec);
+                skipping = parsed.is_err();
+                parsed.map(|parsed| probe = Some(parsed))
+            }
+            _ if skipping => Ok(()),
+            _ => match probe.as_mut() {
+                Some(probe) => parse_directive(probe, directive, spec),
+                None => Err(format!("{} before the first Probe", directive)),
+            },
+        };
+        if let Err(e) = result {
+            errors.push(format!("{}:{}: {}", source, i + 1, e));
+        }
+    }
+    database.probes.extend(probe);
+
+    (database, errors)
+}
+
+/// Applies a directive to the probe it follows.
+fn parse_directive(probe: &mut ServiceProbe, directive: &str, spec: &str) -> Result<(), String> {
+    match directive {
+        "match" => probe.matches.push(parse_match(spec, false)?),
+        "softmatch" => probe.matches.push(parse_match(spec, true)?),
+        "ports" => probe.ports.extend(parse_ports(spec)?),
+        "sslports" => probe.ssl_ports.extend(parse_ports(spec)?),
+        "rarity" => {
+            probe.rarity = spec
+                .parse()
+                .ok()
+                .filter(|rarity| (1..=9).contains(rarity))
+                .ok_or_else(|| format!("Invalid rarity {}", spec))?
+        }
+        "totalwaitms" => {
+            let wait = spec.parse().map_err(|_| format!("Invalid wait {}", spec))?;
+            probe.wait = Duration::from_millis(wait);
+        }
+        "tcpwrappedms" => {}
+        "fallback" => probe.fallback.extend(
+            spec.split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string),
+        ),
+        _ => return Err(format!("Unknown directive {}", directive)),
+    }
+
+    Ok(())
+}
+
+/// Parses the rest of a `Probe` line, e.g. `TCP GetRequest q|GET / HTTP/1.0\r\n\r\n|`.
+fn parse_probe(spec: &str) -> Result<ServiceProbe, String> {
+    let (protocol, spec) = next_word(spec);
+    let protocol = match protocol {
+        "TCP" => ScanProtocol::Tcp,
+        "UDP" => ScanProtocol::Udp,
+        _ => return Err(format!("Invalid probe protocol {}", protocol)),
+    };
+    let (name, spec) = next_word(spec);
+    if name.is_empty() {
+        return Err("Missing probe name".to_string());
+    }
+    let spec = spec
+        .strip_prefix('q')
+        .ok_or_else(|| format!("Probe string of {} must start with q", name))?;
+    let (payload, flags) = delimited(spec)?;
+    match flags.trim() {
+        // Only tells nmap to leave the payload out of its UDP port scans.
+        "" | "no-payload" => {}
+        flags => return Err(format!("Unknown probe option {}", flags)),
+    }
+
+    Ok(ServiceProbe {
+        name: name.to_string(),
+        protocol,
+        payload: unescape(payload)?,
+        ports: Vec::new(),
+        ssl_ports: Vec::new(),
+        rarity: DEFAULT_RARITY,
+        fallback: Vec::new(),
+        wait: DEFAULT_PROBE_WAIT,
+        matches: Vec::new(),
+    })
+}
+
+/// Parses the rest of a `match` or `softmatch` line, e.g.
+/// `ftp m/^220 \(vsFTPd ([\w.]+)\)/ p/vsftpd/ v/$1/`.
+fn parse_match(spec: &str, soft: bool) -> Result<ServiceMatch, String> {
+    let (service, spec) = next_word(spec);
+    let spec = spec
+        .strip_prefix('m')
+        .ok_or_else(|| format!("Pattern of {} must start with m", service))?;
+    let (pattern, spec) = delimited(spec)?;
+    let flags_end = spec
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(spec.len());
+    let (flags, mut spec) = spec.split_at(flags_end);
+    if let Some(flag) = flags.chars().find(|flag| !matches!(flag, 'i' | 's')) {
+        return Err(format!("Unknown pattern option {}", flag));
+    }
+
+    let pattern = match flags.is_empty() {
+        true => translate_pattern(pattern),
+        false => format!("(?{}){}", flags, translate_pattern(pattern)),
+    };
+    let mut service_match = match soft {
+        true => ServiceMatch::soft(service, &pattern)?,
+        false => ServiceMatch::new(service, &pattern)?,
+    };
+
+    loop {
+        spec = spec.trim_start();
+        if spec.is_empty() {
+            break;
+        }
+        if let Some(rest) = spec.strip_prefix("cpe:") {
+            let (template, rest) = delimited(rest)?;
+            service_match.cpe.push(format!("cpe:/{}", template));
+            // CPE names may be followed by flags, which only matter to nmap.
+            spec = rest.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+            continue;
+        }
+
+        let mut chars = spec.chars();
+        let field = chars.next().unwrap_or_default();
+        let (template, rest) = delimited(chars.as_str())?;
+        let slot = match field {
+            'p' => &mut service_match.product,
+            'v' => &mut service_match.version,
+            'i' => &mut service_match.extra_info,
+            'h' => &mut service_match.hostname,
+            'o' => &mut service_match.os,
+            'd' => &mut service_match.device_type,
+            _ => return Err(format!("Unknown version field {}", field)),
+        };
+        *slot = Some(template.to_string());
+        spec = rest;
+    }
+
+    Ok(service_match)
+}
+
+/// Parses a comma separated list of ports and port ranges.
+fn parse_ports(spec: &str) -> Result<Vec<u16>, String> {
+    let mut ports = Vec::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let range: PortRange = entry
+            .parse()
+            .map_err(|e| format!("Invalid port {}: {}", entry, e))?;
+        ports.extend(range.start..=range.end);
+    }
+
+    Ok(ports)
+}
+
+/// Splits off the first whitespace separated word of a line.
+fn next_word(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    s.split_once(char::is_whitespace)
+        .map_or((s, ""), |(word, rest)| (word, rest.trim_start()))
+}
+
+/// Splits `|text|rest` into `text` and `rest`, the first character being the
+/// delimiter.
+fn delimited(s: &str) -> Result<(&str, &str), String> {
+    let delimiter = s
+        .chars()
+        .next()
+        .ok_or_else(|| "Missing delimiter".to_string())?;
+    let s = &s[delimiter.len_utf8()..];
+    let end = s
+        .find(delimiter)
+        .ok_or_else(|| format!("Missing closing {}", delimiter))?;
+
+    Ok((&s[..end], &s[end + delimiter.len_utf8()..]))
+}
+
+/// Decodes the C style escapes of a probe string, such as `\r`, `\0` and `\xff`.
+fn unescape(s: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut chars = s.bytes();
+    while let Some(byte) = chars.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+
+        bytes.push(match chars.next() {
+            Some(b'0') => 0,
+            Some(b'a') => 0x07,
+            Some(b'b') => 0x08,
+            Some(b'f') => 0x0c,
+            Some(b'n') => b'\n',
+            Some(b'r') => b'\r',
+            Some(b't') => b'\t',
+            Some(b'v') => 0x0b,
+            Some(b'x') => {
+                let digits: Vec<u8> = chars.by_ref().take(2).collect();
+                std::str::from_utf8(&digits)
+                    .ok()
+                    .filter(|digits| digits.len() == 2)
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .ok_or_else(|| "Invalid \\x escape in probe string".to_string())?
+            }
+            Some(other) => other,
+            None => return Err("Probe string ends with a backslash".to_string()),
+        });
+    }
+
+    Ok(bytes)
+}
+
+/// Rewrites the PCRE escapes the `regex` crate lacks: octal escapes such as
+/// `\0` become `\x00` and `\Z` becomes `\n?\z`.
+fn translate_pattern(pattern: &str) -> String {
+    let mut translated = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            translated.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('0') => {
+                let mut value = 0;
+                for _ in 0..2 {
+                    match chars.peek().and_then(|digit| digit.to_digit(8)) {
+                        Some(digit) => {
+                            value = value * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                let _ = write!(translated, "\\x{:02x}", value);
+            }
+            Some('Z') => translated.push_str("\\n?\\z"),
+            Some(other) => {
+                translated.push('\\');
+                translated.push(other);
+            }
+            None => translated.push('\\'),
+        }
+    }
+
+    translated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delimited_accepts_any_delimiter() {
+        assert_eq!(delimited("|a/b|rest"), Ok(("a/b", "rest")));
+        assert_eq!(delimited("=x|y= p"), Ok(("x|y", " p")));
+        assert_eq!(delimited("%%"), Ok(("", "")));
+        assert!(delimited("%abc").is_err());
+        assert!(delimited("").is_err());
+    }
+
+    #[test]
+    fn match_fields_use_their_own_delimiters() {
+        let service_match = parse_match(r"ssh m=^SSH-([\d.]+)-= p%Open/SSH% v,$1,", false).unwrap();
+        let info = service_match.apply(b"SSH-2.0-OpenSSH_9.6").unwrap();
+        assert_eq!(info.name, "ssh");
+        assert_eq!(info.product.as_deref(), Some("Open/SSH"));
+        assert_eq!(info.version.as_deref(), Some("2.0"));
+    }
+
+    #[test]
+    fn unescape_decodes_c_escapes() {
+        assert_eq!(
+            unescape(r"GET\r\n\0\x41\\\|"),
+            Ok(b"GET\r\n\0A\\|".to_vec())
+        );
+        assert!(unescape(r"\x4").is_err());
+        assert!(unescape(r"\x").is_err());
+        assert!(unescape(r"\xzz").is_err());
+        assert!(unescape("trailing\\").is_err());
+    }
+
+    #[test]
+    fn translate_pattern_rewrites_octal_escapes_and_end_anchor() {
+        let cases = [
+            (r"\0", r"\x00"),
+            (r"\01", r"\x01"),
+            (r"\012", r"\x0a"),
+            (r"\0123", r"\x0a3"),
+            (r"\08", r"\x008"),
+            (r"^220\Z", r"^220\n?\z"),
+            (r"\d+\.\w", r"\d+\.\w"),
+        ];
+        for (pattern, translated) in cases {
+            assert_eq!(translate_pattern(pattern), translated, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn cpe_fields_may_be_followed_by_flags() {
+        let service_match = parse_match(
+            r"ftp m|^220 vsFTPd ([\d.]+)| p/vsftpd/ cpe:/a:beasts:vsftpd:$1/a cpe:|o:linux:linux_kernel| i/x/",
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            service_match.cpe,
+            ["cpe:/a:beasts:vsftpd:$1", "cpe:/o:linux:linux_kernel"]
+        );
+        let info = service_match.apply(b"220 vsFTPd 3.0.5").unwrap();
+        assert_eq!(
+            info.cpe,
+            ["cpe:/a:beasts:vsftpd:3.0.5", "cpe:/o:linux:linux_kernel"]
+        );
+        assert_eq!(info.extra_info.as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn directives_after_an_invalid_probe_are_skipped() {
+        let text = "\
+Probe TCP NULL q||
+match ssh m|^SSH-|
+Probe XYZ Broken q||
+match ftp m|^220|
+ports 21
+Probe TCP GetRequest q|GET / HTTP/1.0\\r\\n\\r\\n|
+match http m|^HTTP/1\\.[01]|
+";
+        let (database, errors) = parse(text, "test");
+        assert_eq!(errors, ["test:3: Invalid probe protocol XYZ"]);
+        assert_eq!(database.probes.len(), 2);
+        assert_eq!(database.probes[0].name, "NULL");
+        assert_eq!(database.probes[0].matches.len(), 1);
+        assert!(database.probes[0].ports.is_empty());
+        assert_eq!(database.probes[1].payload, b"GET / HTTP/1.0\r\n\r\n");
+        assert_eq!(database.probes[1].matches.len(), 1);
+    }
+
+    #[test]
+    fn lines_that_are_not_utf8_are_skipped() {
+        let bytes = b"# comment \xff\n\
+Probe TCP NULL q||\n\
+match ssh m|^SSH-\xff|\n\
+Probe TCP Bad\xff q||\n\
+match ftp m|^220|\n";
+        let (database, errors) = parse_bytes(bytes, "test");
+        assert_eq!(
+            errors,
+            [
+                "test:3: Line is not valid UTF-8",
+                "test:4: Line is not valid UTF-8"
+            ]
+        );
+        assert_eq!(database.probes.len(), 1);
+        assert!(database.probes[0].matches.is_empty());
+    }
+
+    #[test]
+    fn templates_expand_group_references() {
+        let service_match = parse_match(
+            r#"x m|^([^ ]+) ([^ ]+) (..)$|s p/$P(1)/ v/$SUBST(2,"_",".")/ i/port $I(3,">")/ h/$1/ o/$I(3,"<")/"#,
+            false,
+        )
+        .unwrap();
+        let info = service_match.apply(b"a\x01b 1_2_3 \x00\x50").unwrap();
+        assert_eq!(info.product.as_deref(), Some("ab"));
+        assert_eq!(info.version.as_deref(), Some("1.2.3"));
+        assert_eq!(info.extra_info.as_deref(), Some("port 80"));
+        assert_eq!(info.hostname.as_deref(), Some(r"a\x01b"));
+        assert_eq!(info.os.as_deref(), Some("20480"));
+    }
+}
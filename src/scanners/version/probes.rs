@@ -0,0 +1,185 @@
+//! The built-in service probe database.
+//!
+//! Covers the services most often found on open ports. Services that greet
+//! the client are matched from the NULL probe, HTTP servers from a `GET`
+//! request, and TLS services from a handshake. Within a probe, specific
+//! products are listed before the generic matches of their protocol.
+use super::{ServiceDatabase, ServiceMatch, ServiceProbe, DEFAULT_PROBE_WAIT};
+use crate::scanners::ScanProtocol;
+
+/// SSLv3 ClientHello, answered with a handshake or an alert by any TLS service.
+const SSL_SESSION_REQ: &[u8] = b"\x16\x03\x00\x00\x53\x01\x00\x00\x4f\x03\x00\x3f\x47\xd7\xf7\
+    \xba\x2c\xee\xea\xb2\x60\x7e\xf3\x00\xfd\x82\x7b\xb9\xd5\x96\xc8\x77\x9b\xe6\xc4\xdb\x3c\
+    \x3d\xdb\x6f\xef\x10\x6e\x00\x00\x28\x00\x16\x00\x13\x00\x0a\x00\x66\x00\x05\x00\x04\x00\
+    \x65\x00\x64\x00\x63\x00\x62\x00\x61\x00\x60\x00\x15\x00\x12\x00\x09\x00\x14\x00\x11\x00\
+    \x08\x00\x06\x00\x03\x01\x00";
+
+/// DNS query for the `version.bind` TXT record in the CHAOS class.
+const DNS_VERSION_BIND_REQ: &[u8] = b"\x00\x06\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+    \x07version\x04bind\x00\x00\x10\x00\x03";
+
+/// Creates a full match of a built-in pattern.
+fn hard(service: &str, pattern: &str) -> ServiceMatch {
+    ServiceMatch::new(service, pattern).expect("Invalid built-in service pattern")
+}
+
+/// Creates a soft match of a built-in pattern.
+fn soft(service: &str, pattern: &str) -> ServiceMatch {
+    ServiceMatch::soft(service, pattern).expect("Invalid built-in service pattern")
+}
+
+/// Creates a common probe with the default wait.
+fn probe(
+    name: &str,
+    protocol: ScanProtocol,
+    payload: &[u8],
+    ports: &[u16],
+    matches: Vec<ServiceMatch>,
+) -> ServiceProbe {
+    ServiceProbe {
+        name: name.to_string(),
+        protocol,
+        payload: payload.to_vec(),
+        ports: ports.to_vec(),
+        ssl_ports: Vec::new(),
+        rarity: 1,
+        fallback: Vec::new(),
+        wait: DEFAULT_PROBE_WAIT,
+        matches,
+    }
+}
+
+/// Matches of the greetings services send before the client says anything.
+fn greeting_matches() -> Vec<ServiceMatch> {
+    vec![
+        hard("ssh", r"^SSH-([\d.]+)-OpenSSH[_-]([\w.]+)")
+            .with_product("OpenSSH")
+            .with_version("$2")
+            .with_extra_info("protocol $1"),
+        hard("ssh", r"^SSH-([\d.]+)-dropbear_([\w.]+)")
+            .with_product("Dropbear sshd")
+            .with_version("$2")
+            .with_extra_info("protocol $1"),
+        hard("ssh", r"^SSH-([\d.]+)-([^\s]+)")
+            .with_product("$2")
+            .with_extra_info("protocol $1"),
+        hard("ftp", r"^220 \(vsFTPd ([\w.]+)\)")
+            .with_product("vsftpd")
+            .with_version("$1"),
+        hard("ftp", r"(?s)^220[- ].*ProFTPD ([\w.]+)")
+            .with_product("ProFTPD")
+            .with_version("$1"),
+        hard("ftp", r"^220-+ Welcome to Pure-FTPd").with_product("Pure-FTPd"),
+        hard("smtp", r"^220 [-\w.]+ ESMTP Postfix").with_product("Postfix smtpd"),
+        hard("smtp", r"^220 [-\w.]+ ESMTP Exim ([\d.]+)")
+            .with_product("Exim smtpd")
+            .with_version("$1"),
+        hard("smtp", r"^220 [-\w.]+ ESMTP Sendmail ([\w.]+)")
+            .with_product("Sendmail")
+            .with_version("$1"),
+        soft("smtp", r"(?i)^220[- ][^\r\n]*smtp"),
+        soft("ftp", r"(?is)^220[- ].*ftp"),
+        hard("pop3", r"^\+OK Dovecot").with_product("Dovecot pop3d"),
+        soft("pop3", r"^\+OK"),
+        hard("imap", r"^\* OK (?:\[CAPABILITY [^\]]*\] )?Dovecot").with_product("Dovecot imapd"),
+        soft("imap", r"^\* OK"),
+        hard(
+            "mysql",
+            r"(?s)^.\x00\x00\x00\x0a(?:5\.5\.5-)?([\d.]+)-MariaDB",
+        )
+        .with_product("MariaDB")
+        .with_version("$1"),
+        hard("mysql", r"(?s)^.\x00\x00\x00\x0a([\d.]+)[^\x00]*\x00")
+            .with_product("MySQL")
+            .with_version("$1"),
+        hard("vnc", r"^RFB (\d{3}\.\d{3})\n").with_extra_info("protocol $1"),
+        hard("rsync", r"^@RSYNCD: ([\d.]+)\n").with_extra_info("protocol version $1"),
+        soft("telnet", r"^\xff[\xfb-\xfe]"),
+    ]
+}
+
+/// Matches of HTTP responses, identifying the server from its `Server` header.
+fn http_matches() -> Vec<ServiceMatch> {
+    vec![
+        hard(
+            "http",
+            r"(?s)^HTTP/1\.[01] \d\d\d .*?\r\nServer: nginx/([\d.]+)",
+        )
+        .with_product("nginx")
+        .with_version("$1"),
+        hard(
+            "http",
+            r"(?s)^HTTP/1\.[01] \d\d\d .*?\r\nServer: Apache/([\d.]+)(?: \(([^)\r\n]+)\))?",
+        )
+        .with_product("Apache httpd")
+        .with_version("$1")
+        .with_extra_info("$2"),
+        hard(
+            "http",
+            r"(?s)^HTTP/1\.[01] \d\d\d .*?\r\nServer: lighttpd/([\d.]+)",
+        )
+        .with_product("lighttpd")
+        .with_version("$1"),
+        hard(
+            "http",
+            r"(?s)^HTTP/1\.[01] \d\d\d .*?\r\nServer: Microsoft-IIS/([\d.]+)",
+        )
+        .with_product("Microsoft IIS httpd")
+        .with_version("$1"),
+        hard(
+            "http",
+            r"(?s)^HTTP/1\.[01] \d\d\d .*?\r\nServer: SimpleHTTP/([\d.]+) Python/([\d.]+)",
+        )
+        .with_product("SimpleHTTPServer")
+        .with_version("$1")
+        .with_extra_info("Python $2"),
+        hard(
+            "http",
+            r"(?s)^HTTP/1\.[01] \d\d\d .*?\r\nServer: ([^\r\n]+)",
+        )
+        .with_product("$1"),
+        soft("http", r"^HTTP/1\.[01] \d\d\d"),
+        soft("rtsp", r"^RTSP/1\.0 \d\d\d"),
+    ]
+}
+
+/// Builds the built-in database.
+pub fn builtin() -> ServiceDatabase {
+    ServiceDatabase {
+        excluded: None,
+        probes: vec![
+            probe("NULL", ScanProtocol::Tcp, b"", &[], greeting_matches()),
+            probe(
+                "GetRequest",
+                ScanProtocol::Tcp,
+                b"GET / HTTP/1.0\r\n\r\n",
+                &[80, 81, 591, 8000, 8008, 8080, 8081, 8088, 8888],
+                http_matches(),
+            ),
+            probe(
+                "SSLSessionReq",
+                ScanProtocol::Tcp,
+                SSL_SESSION_REQ,
+                &[443, 465, 636, 853, 989, 990, 992, 993, 995, 8443],
+                vec![
+                    hard("ssl", r"(?s)^\x16\x03[\x00-\x04]..\x02"),
+                    hard("ssl", r"(?s)^\x15\x03[\x00-\x04]\x00\x02"),
+                ],
+            ),
+            probe(
+                "GenericLines",
+                ScanProtocol::Tcp,
+                b"\r\n\r\n",
+                &[],
+                http_matches(),
+            ),
+            probe(
+                "DNSVersionBindReq",
+                ScanProtocol::Udp,
+                DNS_VERSION_BIND_REQ,
+                &[53],
+                vec![soft("domain", r"(?s)^\x00\x06[\x81\x85]")],
+            ),
+        ],
+    }
+}